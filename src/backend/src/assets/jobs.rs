@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use ic_cdk::api::time;
+
+use crate::config::stores::trash_retention_ns;
+
+use super::mutations::purge_trash;
+
+/// How often trashed assets are checked against the retention period
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts the job that purges trashed assets older than the configured retention.
+/// Timers don't survive upgrades, so this runs from both init and post_upgrade.
+pub fn schedule_trash_purge() {
+    ic_cdk_timers::set_timer_interval(TRASH_PURGE_INTERVAL, purge_expired_trash);
+}
+
+fn purge_expired_trash() {
+    let cutoff = time().saturating_sub(trash_retention_ns());
+    let purged = purge_trash(|trashed| trashed.trashed_at <= cutoff);
+    if purged > 0 {
+        ic_cdk::println!("Purged {} expired assets from trash", purged);
+    }
+}
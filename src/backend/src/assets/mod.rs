@@ -1,4 +1,7 @@
+pub mod jobs;
 pub mod models;
 pub mod mutations;
 pub mod queries;
 pub mod stores;
+pub mod tree;
+pub mod versions;
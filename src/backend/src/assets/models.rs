@@ -1,3 +1,6 @@
+use crate::common::memory::impl_candid_storable;
+use crate::common::utils::time::to_rfc3339;
+use crate::migrations::v2_numeric_timestamps::{LegacyAsset, LegacyFolder};
 use candid::{CandidType, Principal};
 use serde::Deserialize;
 
@@ -8,8 +11,10 @@ pub struct Folder {
     pub description: String,
     pub client_id: String,
     pub owner_id: Principal,
-    pub date_added: String,
-    pub last_updated: String,
+    pub date_added: u64,
+    pub last_updated: u64,
+    /// Containing folder, `None` for top-level folders
+    pub parent_uuid: Option<String>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -21,10 +26,82 @@ pub struct Asset {
     pub ipfs_hash: String,
     pub size_mb: f64,
     pub owner_id: Principal,
+    pub date_added: u64,
+    pub last_updated: u64,
+    /// Asset this one was copied from by `copy_assets`
+    pub copied_from: Option<String>,
+}
+
+/// A folder as listed by `client_folders`
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct FolderListing {
+    pub folder: Folder,
+    /// Only set when the listing was asked to `render_timestamps`
+    pub rendered: Option<RenderedTimestamps>,
+}
+
+/// An asset as listed by `client_assets` and `client_folder_assets`
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AssetListing {
+    pub asset: Asset,
+    /// Only set when the listing was asked to `render_timestamps`
+    pub rendered: Option<RenderedTimestamps>,
+}
+
+/// `date_added` and `last_updated` as RFC 3339 UTC timestamps
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RenderedTimestamps {
     pub date_added: String,
     pub last_updated: String,
 }
 
+impl RenderedTimestamps {
+    pub fn new(date_added: u64, last_updated: u64) -> Self {
+        RenderedTimestamps {
+            date_added: to_rfc3339(date_added),
+            last_updated: to_rfc3339(last_updated),
+        }
+    }
+}
+
+/// Immutable snapshot of an asset's content, appended on every change
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AssetVersion {
+    /// 1-based position in the asset's history
+    pub number: u32,
+    pub name: String,
+    pub description: String,
+    pub ipfs_hash: String,
+    pub size_mb: f64,
+    pub editor: Principal,
+    pub timestamp: u64,
+    pub change_note: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Deserialize)]
+pub struct AssetVersionHistory {
+    pub versions: Vec<AssetVersion>,
+}
+
+/// An asset moved to its owner's trash by `delete_asset`. Trashed assets live outside
+/// `ASSETS`, so listings and storage usage ignore them until they are restored.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TrashedAsset {
+    pub asset: Asset,
+    pub trashed_at: u64,
+}
+
+/// What `delete_folder` does with the subfolders and assets still inside the folder
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum FolderDeletePolicy {
+    /// Delete every subfolder and move every contained asset to the trash
+    Cascade,
+    /// Move direct subfolders and contained assets into another folder of the same owner
+    MoveTo(String),
+    /// Refuse to delete a folder that still contains subfolders or assets
+    RejectIfNotEmpty,
+}
+
 #[derive(Clone, Debug, Default, CandidType, Deserialize)]
 pub struct Paginated<T> {
     pub offset: Option<usize>,
@@ -44,6 +121,8 @@ pub struct AssetFilter {
 pub struct AssetQueryOptions {
     pub filter: Option<AssetFilter>,
     pub ordering: Option<AssetOrdering>,
+    /// Render the timestamps of the listed assets
+    pub render_timestamps: Option<bool>,
 }
 
 #[derive(Clone, Debug, Default, CandidType, Deserialize)]
@@ -56,12 +135,18 @@ pub struct AssetOrdering {
 pub struct FolderQueryOptions {
     pub filter: Option<FolderFilter>,
     pub ordering: Option<FolderOrdering>,
+    /// Render the timestamps of the listed folders
+    pub render_timestamps: Option<bool>,
 }
 
 #[derive(Clone, Debug, Default, CandidType, Deserialize)]
 pub struct FolderFilter {
     pub name: Option<String>,
     pub description: Option<String>,
+    /// Only list the direct children of this folder
+    pub parent_uuid: Option<String>,
+    /// Only list top-level folders
+    pub root_only: Option<bool>,
 }
 
 #[derive(Clone, Debug, Default, CandidType, Deserialize)]
@@ -69,3 +154,10 @@ pub struct FolderOrdering {
     pub date_added: Option<bool>,
     pub last_updated: Option<bool>,
 }
+
+impl_candid_storable!(
+    Folder | LegacyFolder,
+    Asset | LegacyAsset,
+    TrashedAsset,
+    AssetVersionHistory,
+);
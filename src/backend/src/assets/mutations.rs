@@ -1,97 +1,490 @@
-use crate::{common::utils::uuid::generate_unique_id, users::stores::CLIENTS};
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::utils::uuid::{IdKind, generate_unique_id};
+use crate::usage::guards::metered;
+use crate::usage::stores::{adjust_storage, release_storage, size_kb, validate_size_mb};
+use crate::users::guards::require_client;
+use candid::Principal;
 use ic_cdk::api::time;
-use ic_cdk::{caller, update};
+use ic_cdk::update;
 
 use super::{
-    models::{Asset, Folder},
-    stores::{ASSETS, FOLDERS},
+    models::{Asset, Folder, FolderDeletePolicy, TrashedAsset},
+    stores::{ASSETS, FOLDERS, TRASH},
+    tree::{child_folders, descendant_folders, is_same_or_ancestor, validate_folder_name},
+    versions::{asset_history, record_version, remove_history},
 };
 
 #[update]
-fn create_update_folder(input: Folder) -> Result<Folder, String> {
-    let user_principal = caller();
+fn create_update_folder(input: Folder) -> BackendResult<Folder> {
+    metered(|client| {
+        let user_principal = client.principal;
 
-    CLIENTS.with(|clients| {
-        let clients = clients.borrow();
-        if !clients.contains_key(&user_principal) {
-            Err("You are not authorized to perform this action".to_string())
-        } else {
+        if let Some(mut folder) = FOLDERS.with(|folders| folders.borrow().get(&input.uuid)) {
+            // Update existing folder, its parent only changes through move_folder
+            if folder.owner_id != user_principal {
+                return Err(BackendError::Unauthorized);
+            }
+            if folder.name != input.name {
+                validate_folder_name(
+                    &user_principal,
+                    folder.parent_uuid.as_deref(),
+                    &input.name,
+                    Some(&folder.uuid),
+                )?;
+            }
+            folder.name = input.name;
+            folder.description = input.description;
+            folder.last_updated = time();
             FOLDERS.with(|folders| {
-                let mut folders = folders.borrow_mut();
+                folders
+                    .borrow_mut()
+                    .insert(folder.uuid.clone(), folder.clone())
+            });
+            Ok(folder)
+        } else {
+            // Create new folder
+            if let Some(parent_uuid) = &input.parent_uuid {
+                owned_folder(parent_uuid, &user_principal)?;
+            }
+            validate_folder_name(
+                &user_principal,
+                input.parent_uuid.as_deref(),
+                &input.name,
+                None,
+            )?;
 
-                if folders.contains_key(&input.uuid) {
-                    // Update existing folder
-                    let folder = folders.get_mut(&input.uuid).unwrap();
-                    folder.name = input.name;
-                    folder.description = input.description;
-                    folder.last_updated = time().to_string();
-                    Ok(folder.clone())
-                } else {
-                    // Create new folder
-                    let new_uuid = generate_unique_id();
-                    let new_folder = Folder {
-                        uuid: new_uuid.clone(),
-                        name: input.name,
-                        owner_id: user_principal,
-                        description: input.description,
-                        client_id: user_principal.to_string(),
-                        date_added: time().to_string(),
-                        last_updated: time().to_string(),
-                    };
-                    folders.insert(new_uuid, new_folder.clone());
-                    Ok(new_folder)
-                }
-            })
+            let new_uuid = generate_unique_id(IdKind::Folder);
+            let new_folder = Folder {
+                uuid: new_uuid.clone(),
+                name: input.name,
+                owner_id: user_principal,
+                description: input.description,
+                client_id: user_principal.to_string(),
+                date_added: time(),
+                last_updated: time(),
+                parent_uuid: input.parent_uuid,
+            };
+            FOLDERS.with(|folders| folders.borrow_mut().insert(new_uuid, new_folder.clone()));
+            Ok(new_folder)
         }
     })
 }
 
-/// Create or Update an Asset
+/// Moves one of the caller's folders under `parent_uuid`, or to the top level when
+/// `None`. A folder can't be moved into itself or any of its subfolders.
 #[update]
-fn create_update_asset(input: Asset) -> Result<Asset, String> {
-    let user_principal = caller();
+fn move_folder(uuid: String, parent_uuid: Option<String>) -> BackendResult<Folder> {
+    metered(|client| {
+        let mut folder = owned_folder(&uuid, &client.principal)?;
+
+        if let Some(parent_uuid) = &parent_uuid {
+            owned_folder(parent_uuid, &client.principal)?;
+            if is_same_or_ancestor(&uuid, parent_uuid) {
+                return Err(BackendError::validation(
+                    "parent_uuid",
+                    "cannot move a folder into itself or one of its subfolders",
+                ));
+            }
+        }
+        validate_folder_name(
+            &client.principal,
+            parent_uuid.as_deref(),
+            &folder.name,
+            Some(&uuid),
+        )?;
+
+        folder.parent_uuid = parent_uuid;
+        folder.last_updated = time();
+        FOLDERS.with(|folders| folders.borrow_mut().insert(uuid, folder.clone()));
+
+        Ok(folder)
+    })
+}
 
-    CLIENTS.with(|clients| {
-        let clients = clients.borrow();
-        if !clients.contains_key(&user_principal) {
-            Err("You are not authorized to perform this action".to_string())
+/// Create or Update an Asset. Every call appends the resulting content to the asset's
+/// version history, annotated with `change_note`.
+#[update]
+fn create_update_asset(input: Asset, change_note: Option<String>) -> BackendResult<Asset> {
+    metered(|client| {
+        let user_principal = client.principal;
+
+        if let Some(previous) = ASSETS.with(|assets| assets.borrow().get(&input.uuid)) {
+            // Update existing asset, its folder only changes through move_assets
+            if previous.owner_id != user_principal {
+                return Err(BackendError::Unauthorized);
+            }
+            validate_size_mb(input.size_mb)?;
+            adjust_storage(
+                &user_principal,
+                size_kb(previous.size_mb),
+                size_kb(input.size_mb),
+            )?;
+
+            let asset = Asset {
+                name: input.name,
+                description: input.description,
+                ipfs_hash: input.ipfs_hash,
+                size_mb: input.size_mb,
+                last_updated: time(),
+                ..previous.clone()
+            };
+            ASSETS.with(|assets| {
+                assets
+                    .borrow_mut()
+                    .insert(asset.uuid.clone(), asset.clone())
+            });
+            record_version(&asset, Some(&previous), user_principal, change_note);
+            Ok(asset)
         } else {
+            // Create new asset
+            owned_folder(&input.folder_uuid, &user_principal)?;
+            validate_size_mb(input.size_mb)?;
+            adjust_storage(&user_principal, 0, size_kb(input.size_mb))?;
+
+            let new_uuid = generate_unique_id(IdKind::Asset);
+            let new_asset = Asset {
+                uuid: new_uuid.clone(),
+                name: input.name,
+                owner_id: user_principal,
+                description: input.description,
+                folder_uuid: input.folder_uuid,
+                ipfs_hash: input.ipfs_hash,
+                size_mb: input.size_mb,
+                date_added: time(),
+                last_updated: time(),
+                copied_from: None,
+            };
+            ASSETS.with(|assets| assets.borrow_mut().insert(new_uuid, new_asset.clone()));
+            record_version(&new_asset, None, user_principal, change_note);
+            Ok(new_asset)
+        }
+    })
+}
+
+/// Rolls one of the caller's assets back to the content of version `number`. The
+/// rollback is itself appended as a new version.
+#[update]
+fn restore_asset_version(uuid: String, number: u32) -> BackendResult<Asset> {
+    metered(|client| {
+        let previous = ASSETS
+            .with(|assets| assets.borrow().get(&uuid))
+            .filter(|asset| asset.owner_id == client.principal)
+            .ok_or_else(|| BackendError::not_found("asset", &uuid))?;
+        let version = asset_history(&uuid)
+            .into_iter()
+            .find(|version| version.number == number)
+            .ok_or_else(|| {
+                BackendError::not_found("asset_version", format!("{}#{}", uuid, number))
+            })?;
+        adjust_storage(
+            &client.principal,
+            size_kb(previous.size_mb),
+            size_kb(version.size_mb),
+        )?;
+
+        let asset = Asset {
+            name: version.name,
+            description: version.description,
+            ipfs_hash: version.ipfs_hash,
+            size_mb: version.size_mb,
+            last_updated: time(),
+            ..previous.clone()
+        };
+        ASSETS.with(|assets| assets.borrow_mut().insert(uuid, asset.clone()));
+        record_version(
+            &asset,
+            Some(&previous),
+            client.principal,
+            Some(format!("Restored version {}", number)),
+        );
+
+        Ok(asset)
+    })
+}
+
+/// Moves the caller's assets into `target_folder_uuid`. Nothing is moved unless the
+/// caller owns every asset, its current folder and the target folder.
+#[update]
+fn move_assets(asset_uuids: Vec<String>, target_folder_uuid: String) -> BackendResult<Vec<Asset>> {
+    metered(|client| {
+        owned_folder(&target_folder_uuid, &client.principal)?;
+        let assets = owned_assets(&asset_uuids, &client.principal)?;
+
+        let now = time();
+        let moved: Vec<Asset> = assets
+            .into_iter()
+            .map(|mut asset| {
+                asset.folder_uuid = target_folder_uuid.clone();
+                asset.last_updated = now;
+                asset
+            })
+            .collect();
+        ASSETS.with(|assets| {
+            let mut assets = assets.borrow_mut();
+            for asset in &moved {
+                assets.insert(asset.uuid.clone(), asset.clone());
+            }
+        });
+
+        Ok(moved)
+    })
+}
+
+/// Copies the caller's assets into `target_folder_uuid`. Copies get fresh ids and
+/// reference their original through `copied_from`.
+#[update]
+fn copy_assets(asset_uuids: Vec<String>, target_folder_uuid: String) -> BackendResult<Vec<Asset>> {
+    metered(|client| {
+        owned_folder(&target_folder_uuid, &client.principal)?;
+        let assets = owned_assets(&asset_uuids, &client.principal)?;
+        let total_kb = assets.iter().map(|asset| size_kb(asset.size_mb)).sum();
+        adjust_storage(&client.principal, 0, total_kb)?;
+
+        let now = time();
+        let copies: Vec<Asset> = assets
+            .into_iter()
+            .map(|asset| Asset {
+                uuid: generate_unique_id(IdKind::Asset),
+                folder_uuid: target_folder_uuid.clone(),
+                date_added: now,
+                last_updated: now,
+                copied_from: Some(asset.uuid.clone()),
+                ..asset
+            })
+            .collect();
+        ASSETS.with(|assets| {
+            let mut assets = assets.borrow_mut();
+            for copy in &copies {
+                assets.insert(copy.uuid.clone(), copy.clone());
+            }
+        });
+        for copy in &copies {
+            let note = copy
+                .copied_from
+                .as_ref()
+                .map(|original| format!("Copied from {}", original));
+            record_version(copy, None, client.principal, note);
+        }
+
+        Ok(copies)
+    })
+}
+
+/// Looks up every asset in `uuids`, failing unless `owner` owns each asset and the
+/// folder it currently sits in
+fn owned_assets(uuids: &[String], owner: &Principal) -> BackendResult<Vec<Asset>> {
+    if uuids.is_empty() {
+        return Err(BackendError::validation(
+            "asset_uuids",
+            "must list at least one asset",
+        ));
+    }
+
+    uuids
+        .iter()
+        .map(|uuid| {
+            let asset = ASSETS
+                .with(|assets| assets.borrow().get(uuid))
+                .ok_or_else(|| BackendError::not_found("asset", uuid))?;
+            if asset.owner_id != *owner {
+                return Err(BackendError::Unauthorized);
+            }
+            owned_folder(&asset.folder_uuid, owner)?;
+            Ok(asset)
+        })
+        .collect()
+}
+
+/// Moves one of the caller's assets to their trash
+#[update]
+fn delete_asset(uuid: String) -> BackendResult<TrashedAsset> {
+    let client = require_client()?;
+
+    let asset = ASSETS
+        .with(|assets| assets.borrow().get(&uuid))
+        .ok_or_else(|| BackendError::not_found("asset", &uuid))?;
+    if asset.owner_id != client.principal {
+        return Err(BackendError::Unauthorized);
+    }
+
+    Ok(trash_asset(asset))
+}
+
+/// Brings an asset back from the caller's trash into its original folder, or into
+/// `folder_uuid` when given (needed once the original folder has been deleted)
+#[update]
+fn restore_asset(uuid: String, folder_uuid: Option<String>) -> BackendResult<Asset> {
+    let client = require_client()?;
+
+    let mut trashed = TRASH
+        .with(|trash| trash.borrow().get(&uuid))
+        .filter(|trashed| trashed.asset.owner_id == client.principal)
+        .ok_or_else(|| BackendError::not_found("trashed_asset", &uuid))?;
+    if let Some(folder_uuid) = folder_uuid {
+        trashed.asset.folder_uuid = folder_uuid;
+        trashed.asset.last_updated = time();
+    }
+    owned_folder(&trashed.asset.folder_uuid, &client.principal)?;
+    adjust_storage(&client.principal, 0, size_kb(trashed.asset.size_mb))?;
+
+    TRASH.with(|trash| trash.borrow_mut().remove(&uuid));
+    ASSETS.with(|assets| {
+        assets
+            .borrow_mut()
+            .insert(uuid.clone(), trashed.asset.clone())
+    });
+
+    Ok(trashed.asset)
+}
+
+/// Deletes one of the caller's folders. `policy` decides what happens to the
+/// subfolders and assets inside it, so no asset or folder is left pointing at a
+/// missing folder.
+#[update]
+fn delete_folder(uuid: String, policy: FolderDeletePolicy) -> BackendResult<Folder> {
+    let client = require_client()?;
+    let folder = owned_folder(&uuid, &client.principal)?;
+    let subfolders = child_folders(&client.principal, Some(&uuid));
+    let contained = folder_assets(&uuid);
+
+    match policy {
+        FolderDeletePolicy::RejectIfNotEmpty => {
+            if !subfolders.is_empty() || !contained.is_empty() {
+                return Err(BackendError::validation(
+                    "policy",
+                    format!(
+                        "folder still contains {} folders and {} assets",
+                        subfolders.len(),
+                        contained.len()
+                    ),
+                ));
+            }
+        }
+        FolderDeletePolicy::Cascade => {
+            for descendant in descendant_folders(&client.principal, &uuid) {
+                for asset in folder_assets(&descendant.uuid) {
+                    trash_asset(asset);
+                }
+                FOLDERS.with(|folders| folders.borrow_mut().remove(&descendant.uuid));
+            }
+            for asset in contained {
+                trash_asset(asset);
+            }
+        }
+        FolderDeletePolicy::MoveTo(target_uuid) => {
+            owned_folder(&target_uuid, &client.principal)?;
+            if is_same_or_ancestor(&uuid, &target_uuid) {
+                return Err(BackendError::validation(
+                    "policy",
+                    "cannot move contents into the folder being deleted or its subfolders",
+                ));
+            }
+            for subfolder in &subfolders {
+                validate_folder_name(
+                    &client.principal,
+                    Some(&target_uuid),
+                    &subfolder.name,
+                    Some(&subfolder.uuid),
+                )?;
+            }
+
+            let now = time();
             FOLDERS.with(|folders| {
-                let folders = folders.borrow();
-                if !folders.contains_key(&input.folder_uuid) {
-                    Err("Folder not found".to_string())
-                } else {
-                    ASSETS.with(|assets| {
-                        let mut assets = assets.borrow_mut();
-                        if assets.contains_key(&input.uuid) {
-                            // Update existing asset
-                            let asset = assets.get_mut(&input.uuid).unwrap();
-                            asset.name = input.name;
-                            asset.description = input.description;
-                            asset.ipfs_hash = input.ipfs_hash;
-                            asset.size_mb = input.size_mb;
-                            asset.last_updated = time().to_string();
-                            Ok(asset.clone())
-                        } else {
-                            // Create new asset
-                            let new_uuid = generate_unique_id();
-                            let new_asset = Asset {
-                                uuid: new_uuid.clone(),
-                                name: input.name,
-                                owner_id: user_principal,
-                                description: input.description,
-                                folder_uuid: input.folder_uuid,
-                                ipfs_hash: input.ipfs_hash,
-                                size_mb: input.size_mb,
-                                date_added: time().to_string(),
-                                last_updated: time().to_string(),
-                            };
-                            assets.insert(new_uuid, new_asset.clone());
-                            Ok(new_asset)
-                        }
-                    })
+                let mut folders = folders.borrow_mut();
+                for mut subfolder in subfolders {
+                    subfolder.parent_uuid = Some(target_uuid.clone());
+                    subfolder.last_updated = now;
+                    folders.insert(subfolder.uuid.clone(), subfolder);
                 }
-            })
+            });
+            ASSETS.with(|assets| {
+                let mut assets = assets.borrow_mut();
+                for mut asset in contained {
+                    asset.folder_uuid = target_uuid.clone();
+                    asset.last_updated = now;
+                    assets.insert(asset.uuid.clone(), asset);
+                }
+            });
+            // Keep trashed assets restorable into the surviving folder
+            TRASH.with(|trash| {
+                let mut trash = trash.borrow_mut();
+                let moved: Vec<TrashedAsset> = trash
+                    .values()
+                    .filter(|trashed| trashed.asset.folder_uuid == uuid)
+                    .collect();
+                for mut trashed in moved {
+                    trashed.asset.folder_uuid = target_uuid.clone();
+                    trash.insert(trashed.asset.uuid.clone(), trashed);
+                }
+            });
+        }
+    }
+
+    FOLDERS.with(|folders| folders.borrow_mut().remove(&uuid));
+    Ok(folder)
+}
+
+/// Permanently removes everything in the caller's trash, returning how many assets
+/// were purged
+#[update]
+fn empty_trash() -> BackendResult<u64> {
+    let client = require_client()?;
+
+    Ok(purge_trash(|trashed| {
+        trashed.asset.owner_id == client.principal
+    }))
+}
+
+/// Assets directly inside the folder `uuid`
+fn folder_assets(uuid: &str) -> Vec<Asset> {
+    ASSETS.with(|assets| {
+        assets
+            .borrow()
+            .values()
+            .filter(|asset| asset.folder_uuid == uuid)
+            .collect()
+    })
+}
+
+/// Returns the folder `uuid` if it exists and belongs to `owner`
+pub fn owned_folder(uuid: &str, owner: &Principal) -> BackendResult<Folder> {
+    FOLDERS
+        .with(|folders| folders.borrow().get(&uuid.to_string()))
+        .filter(|folder| folder.owner_id == *owner)
+        .ok_or_else(|| BackendError::not_found("folder", uuid))
+}
+
+/// Moves `asset` from `ASSETS` into the trash, releasing its storage
+pub fn trash_asset(asset: Asset) -> TrashedAsset {
+    let trashed = TrashedAsset {
+        asset,
+        trashed_at: time(),
+    };
+
+    ASSETS.with(|assets| assets.borrow_mut().remove(&trashed.asset.uuid));
+    release_storage(&trashed.asset.owner_id, size_kb(trashed.asset.size_mb));
+    TRASH.with(|trash| {
+        trash
+            .borrow_mut()
+            .insert(trashed.asset.uuid.clone(), trashed.clone())
+    });
+
+    trashed
+}
+
+/// Permanently removes the trashed assets matching `predicate`
+pub fn purge_trash(predicate: impl Fn(&TrashedAsset) -> bool) -> u64 {
+    TRASH.with(|trash| {
+        let mut trash = trash.borrow_mut();
+        let purged: Vec<String> = trash
+            .iter()
+            .filter(|(_, trashed)| predicate(trashed))
+            .map(|(uuid, _)| uuid)
+            .collect();
+        for uuid in &purged {
+            trash.remove(uuid);
+            remove_history(uuid);
         }
+        purged.len() as u64
     })
 }
@@ -1,8 +1,16 @@
 use ic_cdk::query;
 
+use crate::common::errors::{BackendError, BackendResult};
+use crate::sessions::guards::effective_caller;
+
 use super::{
-    models::{Asset, AssetQueryOptions, Folder, FolderQueryOptions, Paginated},
-    stores::{ASSETS, FOLDERS},
+    models::{
+        Asset, AssetListing, AssetQueryOptions, AssetVersion, Folder, FolderListing,
+        FolderQueryOptions, Paginated, RenderedTimestamps, TrashedAsset,
+    },
+    stores::{ASSETS, FOLDERS, TRASH},
+    tree::{breadcrumbs, resolve_folder_path},
+    versions::asset_history,
 };
 
 #[query]
@@ -10,13 +18,12 @@ pub fn client_folder_assets(
     user_id: String,
     folder_id: String,
     opts: Option<Paginated<AssetQueryOptions>>,
-) -> Vec<Asset> {
+) -> Vec<AssetListing> {
     let assets = ASSETS.with(|assets| {
         assets
             .borrow()
             .values()
             .filter(|asset| asset.owner_id.to_string() == user_id && asset.folder_uuid == folder_id)
-            .cloned()
             .collect::<Vec<_>>()
     });
     apply_asset_filters(assets, opts)
@@ -27,33 +34,36 @@ pub fn client_folder(user_id: String, id: String) -> Option<Folder> {
     FOLDERS.with(|folders| {
         folders
             .borrow()
-            .values()
-            .find(|folder| folder.owner_id.to_string() == user_id && folder.uuid == id)
-            .cloned()
+            .get(&id)
+            .filter(|folder| folder.owner_id.to_string() == user_id)
     })
 }
 
 #[query]
-pub fn client_folders(user_id: String, opts: Option<Paginated<FolderQueryOptions>>) -> Vec<Folder> {
+pub fn client_folders(
+    user_id: String,
+    opts: Option<Paginated<FolderQueryOptions>>,
+) -> Vec<FolderListing> {
     let folders = FOLDERS.with(|folders| {
         folders
             .borrow()
             .values()
             .filter(|folder| folder.owner_id.to_string() == user_id)
-            .cloned()
             .collect::<Vec<_>>()
     });
     apply_folder_filters(folders, opts)
 }
 
 #[query]
-pub fn client_assets(user_id: String, opts: Option<Paginated<AssetQueryOptions>>) -> Vec<Asset> {
+pub fn client_assets(
+    user_id: String,
+    opts: Option<Paginated<AssetQueryOptions>>,
+) -> Vec<AssetListing> {
     let assets = ASSETS.with(|assets| {
         assets
             .borrow()
             .values()
             .filter(|asset| asset.owner_id.to_string() == user_id)
-            .cloned()
             .collect::<Vec<_>>()
     });
     apply_asset_filters(assets, opts)
@@ -62,7 +72,11 @@ pub fn client_assets(user_id: String, opts: Option<Paginated<AssetQueryOptions>>
 fn apply_asset_filters(
     mut assets: Vec<Asset>,
     opts: Option<Paginated<AssetQueryOptions>>,
-) -> Vec<Asset> {
+) -> Vec<AssetListing> {
+    let render = opts
+        .as_ref()
+        .and_then(|opts| opts.opts.as_ref())
+        .is_some_and(|o| o.render_timestamps == Some(true));
     if let Some(opts) = opts {
         // Apply filters
         if let Some(filter) = opts.opts.clone().and_then(|o| o.filter) {
@@ -84,16 +98,16 @@ fn apply_asset_filters(
         if let Some(ordering) = opts.opts.and_then(|o| o.ordering) {
             if let Some(date_added) = ordering.date_added {
                 if date_added {
-                    assets.sort_by_key(|asset| asset.date_added.clone());
+                    assets.sort_by_key(|asset| asset.date_added);
                 } else {
-                    assets.sort_by_key(|asset| std::cmp::Reverse(asset.date_added.clone()));
+                    assets.sort_by_key(|asset| std::cmp::Reverse(asset.date_added));
                 }
             }
             if let Some(last_updated) = ordering.last_updated {
                 if last_updated {
-                    assets.sort_by_key(|asset| asset.last_updated.clone());
+                    assets.sort_by_key(|asset| asset.last_updated);
                 } else {
-                    assets.sort_by_key(|asset| std::cmp::Reverse(asset.last_updated.clone()));
+                    assets.sort_by_key(|asset| std::cmp::Reverse(asset.last_updated));
                 }
             }
         }
@@ -111,12 +125,22 @@ fn apply_asset_filters(
     }
 
     assets
+        .into_iter()
+        .map(|asset| AssetListing {
+            rendered: render.then(|| RenderedTimestamps::new(asset.date_added, asset.last_updated)),
+            asset,
+        })
+        .collect()
 }
 
 fn apply_folder_filters(
     mut folders: Vec<Folder>,
     opts: Option<Paginated<FolderQueryOptions>>,
-) -> Vec<Folder> {
+) -> Vec<FolderListing> {
+    let render = opts
+        .as_ref()
+        .and_then(|opts| opts.opts.as_ref())
+        .is_some_and(|o| o.render_timestamps == Some(true));
     if let Some(opts) = opts {
         if let Some(filter) = opts.opts.clone().and_then(|o| o.filter) {
             if let Some(name) = filter.name {
@@ -125,23 +149,92 @@ fn apply_folder_filters(
             if let Some(description) = filter.description {
                 folders.retain(|folder| folder.description.contains(&description));
             }
+            if let Some(parent_uuid) = filter.parent_uuid {
+                folders.retain(|folder| folder.parent_uuid.as_ref() == Some(&parent_uuid));
+            }
+            if filter.root_only == Some(true) {
+                folders.retain(|folder| folder.parent_uuid.is_none());
+            }
         }
         if let Some(ordering) = opts.opts.and_then(|o| o.ordering) {
             if let Some(date_added) = ordering.date_added {
                 if date_added {
-                    folders.sort_by_key(|folder| folder.date_added.clone());
+                    folders.sort_by_key(|folder| folder.date_added);
                 } else {
-                    folders.sort_by_key(|folder| std::cmp::Reverse(folder.date_added.clone()));
+                    folders.sort_by_key(|folder| std::cmp::Reverse(folder.date_added));
                 }
             }
             if let Some(last_updated) = ordering.last_updated {
                 if last_updated {
-                    folders.sort_by_key(|folder| folder.last_updated.clone());
+                    folders.sort_by_key(|folder| folder.last_updated);
                 } else {
-                    folders.sort_by_key(|folder| std::cmp::Reverse(folder.last_updated.clone()));
+                    folders.sort_by_key(|folder| std::cmp::Reverse(folder.last_updated));
                 }
             }
         }
     }
+
     folders
+        .into_iter()
+        .map(|folder| FolderListing {
+            rendered: render
+                .then(|| RenderedTimestamps::new(folder.date_added, folder.last_updated)),
+            folder,
+        })
+        .collect()
+}
+
+/// Lists the caller's trashed assets, most recently trashed first
+#[query]
+pub fn trashed_assets() -> Vec<TrashedAsset> {
+    let user_principal = effective_caller();
+
+    let mut trashed = TRASH.with(|trash| {
+        trash
+            .borrow()
+            .values()
+            .filter(|trashed| trashed.asset.owner_id == user_principal)
+            .collect::<Vec<_>>()
+    });
+    trashed.sort_by_key(|trashed| std::cmp::Reverse(trashed.trashed_at));
+    trashed
+}
+
+/// Resolves a path of folder names such as `/2026/cohort-a` among the caller's folders
+#[query]
+pub fn resolve_path(path: String) -> BackendResult<Folder> {
+    resolve_folder_path(&effective_caller(), &path)
+}
+
+/// The chain of folders from the top level down to the caller's folder `uuid`
+#[query]
+pub fn folder_breadcrumbs(uuid: String) -> BackendResult<Vec<Folder>> {
+    let user_principal = effective_caller();
+    let folder = FOLDERS
+        .with(|folders| folders.borrow().get(&uuid))
+        .filter(|folder| folder.owner_id == user_principal)
+        .ok_or_else(|| BackendError::not_found("folder", &uuid))?;
+
+    Ok(breadcrumbs(folder))
+}
+
+/// Content history of one of the caller's live or trashed assets, oldest first
+#[query]
+pub fn asset_versions(uuid: String) -> BackendResult<Vec<AssetVersion>> {
+    let user_principal = effective_caller();
+    let owner = ASSETS
+        .with(|assets| assets.borrow().get(&uuid).map(|asset| asset.owner_id))
+        .or_else(|| {
+            TRASH.with(|trash| {
+                trash
+                    .borrow()
+                    .get(&uuid)
+                    .map(|trashed| trashed.asset.owner_id)
+            })
+        });
+    if owner != Some(user_principal) {
+        return Err(BackendError::not_found("asset", &uuid));
+    }
+
+    Ok(asset_history(&uuid))
 }
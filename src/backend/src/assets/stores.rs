@@ -1,8 +1,23 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
 
-use super::models::{Asset, Folder};
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::memory::{
+    ASSET_VERSIONS_MEMORY_ID, ASSETS_MEMORY_ID, FOLDERS_MEMORY_ID, Memory, TRASH_MEMORY_ID,
+    get_memory,
+};
+
+use super::models::{Asset, AssetVersionHistory, Folder, TrashedAsset};
 
 thread_local! {
-    pub static FOLDERS: std::cell::RefCell<HashMap<String, Folder>> = std::cell::RefCell::new(HashMap::new());
-    pub static ASSETS: std::cell::RefCell<HashMap<String, Asset>> = std::cell::RefCell::new(HashMap::new());
+    pub static FOLDERS: RefCell<StableBTreeMap<String, Folder, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(FOLDERS_MEMORY_ID)));
+    pub static ASSETS: RefCell<StableBTreeMap<String, Asset, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(ASSETS_MEMORY_ID)));
+    /// Trashed assets of every client, keyed by asset uuid
+    pub static TRASH: RefCell<StableBTreeMap<String, TrashedAsset, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(TRASH_MEMORY_ID)));
+    /// Content history of live and trashed assets, keyed by asset uuid
+    pub static ASSET_VERSIONS: RefCell<StableBTreeMap<String, AssetVersionHistory, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(ASSET_VERSIONS_MEMORY_ID)));
 }
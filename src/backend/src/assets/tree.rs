@@ -0,0 +1,144 @@
+use candid::Principal;
+
+use crate::common::errors::{BackendError, BackendResult};
+
+use super::models::Folder;
+use super::stores::FOLDERS;
+
+/// Folders owned by `owner` whose parent is `parent_uuid` (`None` for top level)
+pub fn child_folders(owner: &Principal, parent_uuid: Option<&str>) -> Vec<Folder> {
+    FOLDERS.with(|folders| {
+        folders
+            .borrow()
+            .values()
+            .filter(|folder| {
+                folder.owner_id == *owner && folder.parent_uuid.as_deref() == parent_uuid
+            })
+            .collect()
+    })
+}
+
+/// Every folder below `uuid`, parents before their children
+pub fn descendant_folders(owner: &Principal, uuid: &str) -> Vec<Folder> {
+    let mut descendants = child_folders(owner, Some(uuid));
+    let mut index = 0;
+    while index < descendants.len() {
+        let children = child_folders(owner, Some(&descendants[index].uuid));
+        descendants.extend(children);
+        index += 1;
+    }
+    descendants
+}
+
+/// The chain of folders from the top level down to and including `folder`
+pub fn breadcrumbs(folder: Folder) -> Vec<Folder> {
+    let mut trail = vec![folder];
+    while let Some(parent_uuid) = trail.last().and_then(|folder| folder.parent_uuid.clone()) {
+        // A parent that is already in the trail means the stored tree is corrupt
+        if trail.iter().any(|folder| folder.uuid == parent_uuid) {
+            break;
+        }
+        match FOLDERS.with(|folders| folders.borrow().get(&parent_uuid)) {
+            Some(parent) => trail.push(parent),
+            None => break,
+        }
+    }
+    trail.reverse();
+    trail
+}
+
+/// Whether `ancestor_uuid` is `uuid` itself or one of its ancestors
+pub fn is_same_or_ancestor(ancestor_uuid: &str, uuid: &str) -> bool {
+    FOLDERS
+        .with(|folders| folders.borrow().get(&uuid.to_string()))
+        .map(breadcrumbs)
+        .is_some_and(|trail| trail.iter().any(|folder| folder.uuid == ancestor_uuid))
+}
+
+/// Checks that `name` can be used for a folder under `parent_uuid`: it must be a
+/// non-empty path segment and unique among its siblings
+pub fn validate_folder_name(
+    owner: &Principal,
+    parent_uuid: Option<&str>,
+    name: &str,
+    exclude_uuid: Option<&str>,
+) -> BackendResult<()> {
+    if name.trim().is_empty() || name.contains('/') {
+        return Err(BackendError::validation(
+            "name",
+            "must be non-empty and must not contain '/'",
+        ));
+    }
+    let taken = child_folders(owner, parent_uuid)
+        .iter()
+        .any(|sibling| sibling.name == name && Some(sibling.uuid.as_str()) != exclude_uuid);
+    if taken {
+        return Err(BackendError::already_exists("folder", name));
+    }
+    Ok(())
+}
+
+/// Resolves a slash separated path of folder names such as `/2026/cohort-a`
+pub fn resolve_folder_path(owner: &Principal, path: &str) -> BackendResult<Folder> {
+    let mut current: Option<Folder> = None;
+
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let parent_uuid = current.as_ref().map(|folder| folder.uuid.as_str());
+        let mut matches = child_folders(owner, parent_uuid)
+            .into_iter()
+            .filter(|folder| folder.name == segment);
+        current = match (matches.next(), matches.next()) {
+            (Some(folder), None) => Some(folder),
+            (Some(_), Some(_)) => {
+                return Err(BackendError::validation(
+                    "path",
+                    format!("more than one folder is named {}", segment),
+                ));
+            }
+            (None, _) => return Err(BackendError::not_found("folder", path)),
+        };
+    }
+
+    current.ok_or_else(|| BackendError::validation("path", "must name at least one folder"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_folder(uuid: &str, name: &str, parent_uuid: Option<&str>) {
+        let folder = Folder {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            client_id: Principal::anonymous().to_string(),
+            owner_id: Principal::anonymous(),
+            date_added: 0,
+            last_updated: 0,
+            parent_uuid: parent_uuid.map(str::to_string),
+        };
+        FOLDERS.with(|folders| folders.borrow_mut().insert(uuid.to_string(), folder));
+    }
+
+    #[test]
+    fn resolves_paths_and_detects_cycles() {
+        let owner = Principal::anonymous();
+        insert_folder("fld_year", "2026", None);
+        insert_folder("fld_cohort", "cohort-a", Some("fld_year"));
+        insert_folder("fld_course", "rust", Some("fld_cohort"));
+
+        let folder = resolve_folder_path(&owner, "/2026/cohort-a/").unwrap();
+        assert_eq!(folder.uuid, "fld_cohort");
+        assert!(resolve_folder_path(&owner, "/2026/cohort-b").is_err());
+
+        let trail: Vec<_> = breadcrumbs(resolve_folder_path(&owner, "2026/cohort-a/rust").unwrap())
+            .into_iter()
+            .map(|folder| folder.uuid)
+            .collect();
+        assert_eq!(trail, ["fld_year", "fld_cohort", "fld_course"]);
+
+        assert!(is_same_or_ancestor("fld_year", "fld_course"));
+        assert!(!is_same_or_ancestor("fld_course", "fld_year"));
+        assert_eq!(descendant_folders(&owner, "fld_year").len(), 2);
+    }
+}
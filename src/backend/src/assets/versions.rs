@@ -0,0 +1,65 @@
+use candid::Principal;
+use ic_cdk::api::time;
+
+use super::models::{Asset, AssetVersion};
+use super::stores::ASSET_VERSIONS;
+
+/// Versions of the asset `uuid`, oldest first
+pub fn asset_history(uuid: &str) -> Vec<AssetVersion> {
+    ASSET_VERSIONS
+        .with(|versions| versions.borrow().get(&uuid.to_string()))
+        .map(|history| history.versions)
+        .unwrap_or_default()
+}
+
+/// Appends the current content of `asset` to its history. Assets created before
+/// versioning first get their existing content recorded as version 1.
+pub fn record_version(
+    asset: &Asset,
+    previous: Option<&Asset>,
+    editor: Principal,
+    change_note: Option<String>,
+) -> AssetVersion {
+    ASSET_VERSIONS.with(|versions| {
+        let mut versions = versions.borrow_mut();
+        let mut history = versions.get(&asset.uuid).unwrap_or_default();
+
+        if history.versions.is_empty()
+            && let Some(previous) = previous
+        {
+            let imported = snapshot(previous, 1, previous.owner_id, previous.last_updated, None);
+            history.versions.push(imported);
+        }
+
+        let number = history.versions.len() as u32 + 1;
+        let version = snapshot(asset, number, editor, time(), change_note);
+        history.versions.push(version.clone());
+        versions.insert(asset.uuid.clone(), history);
+
+        version
+    })
+}
+
+/// Drops the history of a permanently deleted asset
+pub fn remove_history(uuid: &str) {
+    ASSET_VERSIONS.with(|versions| versions.borrow_mut().remove(&uuid.to_string()));
+}
+
+fn snapshot(
+    asset: &Asset,
+    number: u32,
+    editor: Principal,
+    timestamp: u64,
+    change_note: Option<String>,
+) -> AssetVersion {
+    AssetVersion {
+        number,
+        name: asset.name.clone(),
+        description: asset.description.clone(),
+        ipfs_hash: asset.ipfs_hash.clone(),
+        size_mb: asset.size_mb,
+        editor,
+        timestamp,
+        change_note,
+    }
+}
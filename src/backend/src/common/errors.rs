@@ -0,0 +1,58 @@
+use candid::CandidType;
+use serde::Deserialize;
+
+pub type BackendResult<T> = Result<T, BackendError>;
+
+/// Errors returned by every backend endpoint, so clients can match on the variant
+/// instead of the message text
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub enum BackendError {
+    /// The caller has no profile; call `register` first
+    NotRegistered,
+    /// The caller may not perform this action or access this record
+    Unauthorized,
+    NotFound {
+        entity: String,
+        id: String,
+    },
+    AlreadyExists {
+        entity: String,
+        id: String,
+    },
+    /// A usage limit of the caller's subscription package has been reached
+    QuotaExceeded {
+        resource: String,
+        limit: u64,
+    },
+    ValidationFailed {
+        field: String,
+        reason: String,
+    },
+    /// The ledger call failed or the ledger rejected the operation
+    LedgerError(String),
+    /// Another payment of the caller is still awaiting the ledger; retry once it's done
+    OperationInProgress,
+}
+
+impl BackendError {
+    pub fn not_found(entity: &str, id: impl ToString) -> Self {
+        BackendError::NotFound {
+            entity: entity.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn already_exists(entity: &str, id: impl ToString) -> Self {
+        BackendError::AlreadyExists {
+            entity: entity.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn validation(field: &str, reason: impl ToString) -> Self {
+        BackendError::ValidationFailed {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+
+use ic_stable_structures::DefaultMemoryImpl;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+pub const USERS_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const CLIENTS_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const SUBSCRIPTION_PACKAGES_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const CLIENT_SUBSCRIPTIONS_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const FOLDERS_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const ASSETS_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const ROLES_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const TRASH_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const ASSET_VERSIONS_MEMORY_ID: MemoryId = MemoryId::new(10);
+/// Storage usage in fractional MB, superseded by `STORAGE_USAGE_MEMORY_ID` and cleared
+/// in migration 5
+pub const LEGACY_STORAGE_USAGE_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const REQUEST_USAGE_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const REQUEST_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const SESSIONS_MEMORY_ID: MemoryId = MemoryId::new(14);
+/// Single-token prepaid balances, moved to `PREPAID_BALANCES_MEMORY_ID` by migration 4
+pub const LEGACY_PREPAID_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const SUBSCRIPTION_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const INVOICES_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const TRIALS_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const PROMO_CODES_MEMORY_ID: MemoryId = MemoryId::new(19);
+pub const PROMO_REDEMPTIONS_MEMORY_ID: MemoryId = MemoryId::new(20);
+pub const PREPAID_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(21);
+pub const STORAGE_USAGE_MEMORY_ID: MemoryId = MemoryId::new(22);
+pub const SESSION_INVITES_MEMORY_ID: MemoryId = MemoryId::new(23);
+pub const CLIENT_INVOICES_MEMORY_ID: MemoryId = MemoryId::new(24);
+pub const CLIENT_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(25);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+/// Returns the virtual memory reserved for `id`
+pub fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+/// Implements `Storable` for candid types so they can be kept in stable structures.
+///
+/// `Model | LegacyModel` also accepts values written in an older layout, converting them
+/// with `From<LegacyModel>`, so a migration can rewrite them in place.
+macro_rules! impl_candid_storable {
+    ($($model:ty $(| $legacy:ty)?),* $(,)?) => {
+        $(
+            impl ic_stable_structures::Storable for $model {
+                fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                    std::borrow::Cow::Owned(candid::encode_one(self).expect("Failed to encode value"))
+                }
+
+                fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+                    let decoded = candid::decode_one::<Self>(&bytes);
+                    $(
+                        let decoded = decoded
+                            .or_else(|_| candid::decode_one::<$legacy>(&bytes).map(Self::from));
+                    )?
+                    decoded.expect("Failed to decode value")
+                }
+
+                const BOUND: ic_stable_structures::storable::Bound =
+                    ic_stable_structures::storable::Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_candid_storable;
@@ -1 +1,3 @@
+pub mod errors;
+pub mod memory;
 pub mod utils;
@@ -0,0 +1,57 @@
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Renders nanoseconds since the Unix epoch as an RFC 3339 UTC timestamp, e.g.
+/// `2026-10-18T09:30:00.000000000Z`
+pub fn to_rfc3339(timestamp_nanos: u64) -> String {
+    let seconds = timestamp_nanos / NANOS_PER_SECOND;
+    let nanos = timestamp_nanos % NANOS_PER_SECOND;
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let seconds_of_day = seconds % SECONDS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        nanos
+    )
+}
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian (year, month, day)
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01 so leap days fall at the end of each 400-year era
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_rfc3339() {
+        assert_eq!(to_rfc3339(0), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            to_rfc3339(1_709_210_096_123_456_789),
+            "2024-02-29T12:34:56.123456789Z"
+        );
+    }
+}
@@ -1,5 +1,68 @@
-use ic_cdk::api::time;
+use std::cell::RefCell;
+use std::time::Duration;
 
-pub fn generate_unique_id() -> String {
-    format!("{}", time())
+use ic_cdk::api::management_canister::main::raw_rand;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use uuid::{Builder, Uuid};
+
+thread_local! {
+    /// CSPRNG seeded from the management canister's `raw_rand`. Heap only, it is
+    /// reseeded on every install and upgrade.
+    static RNG: RefCell<Option<ChaCha20Rng>> = const { RefCell::new(None) };
+}
+
+/// Entity kinds that get type-tagged identifiers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdKind {
+    Folder,
+    Asset,
+    Client,
+    SubscriptionPackage,
+}
+
+impl IdKind {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            IdKind::Folder => "fld",
+            IdKind::Asset => "ast",
+            IdKind::Client => "cli",
+            IdKind::SubscriptionPackage => "pkg",
+        }
+    }
+}
+
+/// Generates a prefixed random identifier such as `fld_0b7c1c2e-...`
+pub fn generate_unique_id(kind: IdKind) -> String {
+    format!("{}_{}", kind.prefix(), generate_uuid_v4())
+}
+
+/// Generates an RFC 4122 version 4 UUID from the seeded CSPRNG
+pub fn generate_uuid_v4() -> Uuid {
+    let mut bytes = [0u8; 16];
+    RNG.with(|rng| match rng.borrow_mut().as_mut() {
+        Some(rng) => rng.fill_bytes(&mut bytes),
+        None => ic_cdk::trap("Identifier generator is not seeded yet, please retry shortly"),
+    });
+    Builder::from_random_bytes(bytes).into_uuid()
+}
+
+/// Seeds the CSPRNG from `raw_rand` as soon as the canister can make calls. Init and
+/// upgrade hooks can't await, so the seeding runs from a one-off timer and retries
+/// until the management canister answers.
+pub fn schedule_rng_seeding() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(seed_rng()));
+}
+
+async fn seed_rng() {
+    match raw_rand().await {
+        Ok((bytes,)) => {
+            let seed: [u8; 32] = bytes[..32].try_into().expect("raw_rand returns 32 bytes");
+            RNG.with(|rng| *rng.borrow_mut() = Some(ChaCha20Rng::from_seed(seed)));
+        }
+        Err((code, message)) => {
+            ic_cdk::println!("raw_rand failed ({:?}): {}, retrying", code, message);
+            ic_cdk_timers::set_timer(Duration::from_secs(1), || ic_cdk::spawn(seed_rng()));
+        }
+    }
 }
@@ -0,0 +1,4 @@
+pub mod models;
+pub mod mutations;
+pub mod queries;
+pub mod stores;
@@ -0,0 +1,133 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::impl_candid_storable;
+
+/// Mainnet ICP ledger
+pub const ICP_LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+/// Symbol of the mainnet ICP ledger's token
+pub const ICP_TOKEN_SYMBOL: &str = "ICP";
+
+/// Decimals of the mainnet ICP ledger's token
+pub const ICP_TOKEN_DECIMALS: u8 = 8;
+
+/// Default subscription period: 30 days in nanoseconds
+pub const DEFAULT_SUBSCRIPTION_DURATION_NS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Default time trashed assets are kept before being purged: 30 days in nanoseconds
+pub const DEFAULT_TRASH_RETENTION_NS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Default time an expired subscription can still be renewed before the client is
+/// suspended: 3 days in nanoseconds
+pub const DEFAULT_GRACE_PERIOD_NS: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Default length of a one-time trial: 14 days in nanoseconds
+pub const DEFAULT_TRIAL_DURATION_NS: u64 = 14 * 24 * 60 * 60 * 1_000_000_000;
+
+/// ICP ledger transfer fee in e8s
+pub const DEFAULT_LEDGER_FEE_E8S: u64 = 10_000;
+
+/// Deployment settings, passed as the init and upgrade argument and editable by admins
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub struct BackendConfig {
+    pub ledger_canister_id: Principal,
+    pub nft_canister_id: Option<Principal>,
+    /// Granted `Admin` on install, upgrade and config updates
+    pub admins: Vec<Principal>,
+    pub subscription_duration_ns: u64,
+    pub fees: FeeSettings,
+    /// How long trashed assets are kept before being purged, defaults to 30 days
+    pub trash_retention_ns: Option<u64>,
+    /// How long an expired subscription keeps being retried before the client is
+    /// suspended, defaults to 3 days
+    pub grace_period_ns: Option<u64>,
+    /// Free package every registered user gets, and clients fall back to when a paid
+    /// subscription lapses
+    pub free_package_uuid: Option<String>,
+    /// Length of a trial of a paid package, defaults to 14 days
+    pub trial_duration_ns: Option<u64>,
+    /// Symbol of the `ledger_canister_id` token, as its `icrc1_symbol` reports. Only
+    /// optional for the mainnet ICP ledger.
+    pub ledger_symbol: Option<String>,
+    /// Decimals of the `ledger_canister_id` token, as its `icrc1_decimals` reports.
+    /// Only optional for the mainnet ICP ledger.
+    pub ledger_decimals: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub struct FeeSettings {
+    /// Fee the ledger charges per transfer, in the ledger's base units
+    pub ledger_fee: u64,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig {
+            ledger_canister_id: Principal::from_text(ICP_LEDGER_CANISTER_ID).unwrap(),
+            nft_canister_id: None,
+            admins: Vec::new(),
+            subscription_duration_ns: DEFAULT_SUBSCRIPTION_DURATION_NS,
+            fees: FeeSettings {
+                ledger_fee: DEFAULT_LEDGER_FEE_E8S,
+            },
+            trash_retention_ns: None,
+            grace_period_ns: None,
+            free_package_uuid: None,
+            trial_duration_ns: None,
+            ledger_symbol: None,
+            ledger_decimals: None,
+        }
+    }
+}
+
+impl BackendConfig {
+    /// Checks that the token of the configured ledger is described, which the ICP
+    /// defaults only do for the mainnet ICP ledger
+    pub fn validate_ledger_token(&self) -> BackendResult<()> {
+        if self
+            .ledger_symbol
+            .as_ref()
+            .is_some_and(|symbol| symbol.trim().is_empty())
+        {
+            return Err(BackendError::validation(
+                "ledger_symbol",
+                "must not be empty",
+            ));
+        }
+        let is_icp = self.ledger_canister_id.to_text() == ICP_LEDGER_CANISTER_ID;
+        if !is_icp && (self.ledger_symbol.is_none() || self.ledger_decimals.is_none()) {
+            return Err(BackendError::validation(
+                "ledger_canister_id",
+                "ledgers other than ICP need ledger_symbol and ledger_decimals",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl_candid_storable!(BackendConfig);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_ledgers_must_describe_their_token() {
+        let icp = BackendConfig::default();
+        assert!(icp.validate_ledger_token().is_ok());
+
+        let mut other = BackendConfig {
+            ledger_canister_id: Principal::from_slice(&[1]),
+            ..BackendConfig::default()
+        };
+        assert!(other.validate_ledger_token().is_err());
+        other.ledger_symbol = Some("ckUSDC".to_string());
+        assert!(other.validate_ledger_token().is_err());
+        other.ledger_decimals = Some(6);
+        assert!(other.validate_ledger_token().is_ok());
+        other.ledger_symbol = Some(" ".to_string());
+        assert!(other.validate_ledger_token().is_err());
+    }
+}
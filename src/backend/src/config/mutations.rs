@@ -0,0 +1,28 @@
+use ic_cdk::update;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::roles::{guards::require_role, models::Role};
+use crate::subscriptions::jobs::schedule_free_package_backfill;
+
+use super::models::BackendConfig;
+use super::stores::{get_config, set_config, validate_config};
+
+/// Replaces the runtime configuration. Changing the admin list grants `Admin`, so only
+/// owners may do that. A newly set free package is assigned to registered users who
+/// aren't clients yet, in batches after the call returns.
+#[update]
+fn update_config(config: BackendConfig) -> BackendResult<BackendConfig> {
+    let caller_role = require_role(Role::Admin)?;
+
+    if config.admins != get_config().admins && caller_role < Role::Owner {
+        return Err(BackendError::Unauthorized);
+    }
+    validate_config(&config)?;
+
+    let free_package_changed = config.free_package_uuid != get_config().free_package_uuid;
+    set_config(config.clone());
+    if free_package_changed {
+        schedule_free_package_backfill();
+    }
+    Ok(config)
+}
@@ -0,0 +1,13 @@
+use ic_cdk::query;
+
+use crate::common::errors::BackendResult;
+use crate::roles::{guards::require_role, models::Role};
+
+use super::models::BackendConfig;
+use super::stores;
+
+#[query]
+fn get_config() -> BackendResult<BackendConfig> {
+    require_role(Role::Admin)?;
+    Ok(stores::get_config())
+}
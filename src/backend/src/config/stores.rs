@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_stable_structures::StableCell;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::{CONFIG_MEMORY_ID, Memory, get_memory};
+use crate::roles::stores::seed_admins;
+use crate::users::stores::SUBSCRIPTION_PACKAGES;
+
+use super::models::{
+    BackendConfig, DEFAULT_GRACE_PERIOD_NS, DEFAULT_TRASH_RETENTION_NS, DEFAULT_TRIAL_DURATION_NS,
+    ICP_TOKEN_DECIMALS, ICP_TOKEN_SYMBOL,
+};
+
+thread_local! {
+    pub static CONFIG: RefCell<StableCell<BackendConfig, Memory>> = RefCell::new(
+        StableCell::init(get_memory(CONFIG_MEMORY_ID), BackendConfig::default())
+            .expect("Failed to initialize config")
+    );
+}
+
+pub fn get_config() -> BackendConfig {
+    CONFIG.with(|config| config.borrow().get().clone())
+}
+
+/// Stores `new_config` and grants `Admin` to the admins it lists
+pub fn set_config(new_config: BackendConfig) {
+    seed_admins(&new_config.admins);
+    CONFIG.with(|config| {
+        config
+            .borrow_mut()
+            .set(new_config)
+            .expect("Failed to store config");
+    });
+}
+
+/// Checks `config` before it is stored, whether on install, upgrade or update
+pub fn validate_config(config: &BackendConfig) -> BackendResult<()> {
+    config.validate_ledger_token()?;
+    if config.subscription_duration_ns == 0 {
+        return Err(BackendError::validation(
+            "subscription_duration_ns",
+            "must be greater than zero",
+        ));
+    }
+
+    if let Some(free_package_uuid) = &config.free_package_uuid {
+        let package = SUBSCRIPTION_PACKAGES
+            .with(|packages| packages.borrow().get(free_package_uuid))
+            .ok_or_else(|| BackendError::not_found("subscription_package", free_package_uuid))?;
+        if !package.is_free() {
+            return Err(BackendError::validation(
+                "free_package_uuid",
+                "must refer to a package priced at zero",
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn ledger_canister_id() -> Principal {
+    CONFIG.with(|config| config.borrow().get().ledger_canister_id)
+}
+
+/// Symbol of the configured ledger's token
+pub fn ledger_symbol() -> String {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get()
+            .ledger_symbol
+            .clone()
+            .unwrap_or_else(|| ICP_TOKEN_SYMBOL.to_string())
+    })
+}
+
+/// Decimals of the configured ledger's token
+pub fn ledger_decimals() -> u8 {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get()
+            .ledger_decimals
+            .unwrap_or(ICP_TOKEN_DECIMALS)
+    })
+}
+
+pub fn ledger_fee() -> u64 {
+    CONFIG.with(|config| config.borrow().get().fees.ledger_fee)
+}
+
+pub fn subscription_duration_ns() -> u64 {
+    CONFIG.with(|config| config.borrow().get().subscription_duration_ns)
+}
+
+pub fn trash_retention_ns() -> u64 {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get()
+            .trash_retention_ns
+            .unwrap_or(DEFAULT_TRASH_RETENTION_NS)
+    })
+}
+
+pub fn grace_period_ns() -> u64 {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get()
+            .grace_period_ns
+            .unwrap_or(DEFAULT_GRACE_PERIOD_NS)
+    })
+}
+
+pub fn free_package_uuid() -> Option<String> {
+    CONFIG.with(|config| config.borrow().get().free_package_uuid.clone())
+}
+
+pub fn trial_duration_ns() -> u64 {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get()
+            .trial_duration_ns
+            .unwrap_or(DEFAULT_TRIAL_DURATION_NS)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_are_validated_in_full() {
+        assert!(validate_config(&BackendConfig::default()).is_ok());
+
+        let no_duration = BackendConfig {
+            subscription_duration_ns: 0,
+            ..BackendConfig::default()
+        };
+        assert!(validate_config(&no_duration).is_err());
+
+        let missing_free_package = BackendConfig {
+            free_package_uuid: Some("missing".to_string()),
+            ..BackendConfig::default()
+        };
+        assert!(matches!(
+            validate_config(&missing_free_package),
+            Err(BackendError::NotFound { .. })
+        ));
+    }
+}
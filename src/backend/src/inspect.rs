@@ -0,0 +1,234 @@
+use candid::Principal;
+use ic_cdk::api::call::{accept_message, arg_data_raw_size, method_name};
+use ic_cdk::caller;
+
+use crate::roles::{guards::role_of, models::Role};
+use crate::sessions::stores::is_session;
+use crate::users::stores::{CLIENTS, USERS};
+
+/// Who may send an ingress message to a method
+#[derive(Clone, Copy, Debug)]
+enum Access {
+    /// Any non-anonymous principal
+    Authenticated,
+    /// Principals with a profile, see `register`
+    Registered,
+    /// Principals with a `Client` record and the sessions acting for them
+    Client,
+    /// Principals holding at least the given role
+    Role(Role),
+}
+
+struct MethodPolicy {
+    method: &'static str,
+    access: Access,
+    max_arg_bytes: usize,
+}
+
+const SMALL_PAYLOAD: usize = 1024;
+const RECORD_PAYLOAD: usize = 16 * 1024;
+
+/// Ingress policy of every update method. Methods missing here are rejected, so new
+/// update endpoints must be added to this table.
+const METHOD_POLICIES: &[MethodPolicy] = &[
+    MethodPolicy {
+        method: "register",
+        access: Access::Authenticated,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "update_profile",
+        access: Access::Registered,
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "create_update_client_package_subscription",
+        access: Access::Registered,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "start_trial",
+        access: Access::Registered,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "create_update_folder",
+        access: Access::Client,
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "create_update_asset",
+        access: Access::Client,
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "move_folder",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "delete_folder",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "restore_asset_version",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "move_assets",
+        access: Access::Client,
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "copy_assets",
+        access: Access::Client,
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "delete_asset",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "restore_asset",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "empty_trash",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "register_session",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "accept_session",
+        access: Access::Authenticated,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "revoke_session",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "set_auto_renew",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "change_plan",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "claim_deposit",
+        access: Access::Client,
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "create_update_subscription_package",
+        access: Access::Role(Role::Admin),
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "create_update_promo_code",
+        access: Access::Role(Role::Admin),
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "grant_role",
+        access: Access::Role(Role::Admin),
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "revoke_role",
+        access: Access::Role(Role::Admin),
+        max_arg_bytes: SMALL_PAYLOAD,
+    },
+    MethodPolicy {
+        method: "update_config",
+        access: Access::Role(Role::Admin),
+        max_arg_bytes: RECORD_PAYLOAD,
+    },
+];
+
+fn has_access(principal: &Principal, access: Access) -> bool {
+    if *principal == Principal::anonymous() {
+        return false;
+    }
+
+    match access {
+        Access::Authenticated => true,
+        Access::Registered => USERS.with(|users| users.borrow().contains_key(principal)),
+        Access::Client => {
+            CLIENTS.with(|clients| clients.borrow().contains_key(principal))
+                || is_session(principal)
+        }
+        Access::Role(minimum) => role_of(principal) >= minimum,
+    }
+}
+
+/// Rejects ingress messages before the canister pays for executing them. This only
+/// filters ingress update calls, so every endpoint still performs its own checks.
+#[ic_cdk::inspect_message]
+fn inspect_message() {
+    let method = method_name();
+    let Some(policy) = METHOD_POLICIES
+        .iter()
+        .find(|policy| policy.method == method)
+    else {
+        ic_cdk::trap(&format!("Method {} is not callable by ingress", method));
+    };
+
+    if arg_data_raw_size() > policy.max_arg_bytes {
+        ic_cdk::trap(&format!(
+            "Payload for {} exceeds {} bytes",
+            method, policy.max_arg_bytes
+        ));
+    }
+    if !has_access(&caller(), policy.access) {
+        ic_cdk::trap(&format!("Caller may not call {}", method));
+    }
+
+    accept_message();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_match_update_methods() {
+        let interface = crate::export_candid();
+        let service = interface.split_once("service :").unwrap().1;
+        let update_methods = service
+            .split(';')
+            .filter(|signature| signature.contains("->") && !signature.contains(" query"))
+            .filter_map(|signature| signature.split_once(" : ").map(|(name, _)| name.trim()))
+            .filter(|name| !name.is_empty() && !name.contains('('))
+            .collect::<Vec<_>>();
+
+        for method in &update_methods {
+            assert!(
+                METHOD_POLICIES
+                    .iter()
+                    .any(|policy| policy.method == *method),
+                "{} has no ingress policy",
+                method
+            );
+        }
+        for policy in METHOD_POLICIES {
+            assert!(
+                update_methods.contains(&policy.method),
+                "ingress policy for unknown update method {}",
+                policy.method
+            );
+        }
+    }
+}
@@ -1,12 +1,31 @@
 use candid::{Principal, export_service};
 
 use crate::transactions::accounts::Account;
+use crate::transactions::icrc::IcrcAccount;
 use assets::models::*;
+use common::errors::*;
+use config::models::*;
+use migrations::models::*;
+use promotions::models::*;
+use roles::models::*;
+use sessions::models::*;
+use subscriptions::models::*;
+use transactions::models::*;
+use usage::models::*;
 use users::models::*;
 
 pub mod assets;
 pub mod common;
+pub mod config;
+pub mod inspect;
+pub mod lifecycle;
+pub mod migrations;
+pub mod promotions;
+pub mod roles;
+pub mod sessions;
+pub mod subscriptions;
 pub mod transactions;
+pub mod usage;
 pub mod users;
 
 #[ic_cdk::query(name = "__get_candid_interface_tmp_hack")]
@@ -0,0 +1,48 @@
+use crate::assets::jobs::schedule_trash_purge;
+use crate::common::utils::uuid::schedule_rng_seeding;
+use crate::config::{
+    models::BackendConfig,
+    stores::{set_config, validate_config},
+};
+use crate::migrations::{
+    latest_schema_version, run_pending_migrations, stores::set_schema_version,
+    v1_import_stable_save::capture_legacy_state,
+};
+use crate::subscriptions::jobs::{schedule_free_package_backfill, schedule_subscription_renewals};
+use crate::usage::jobs::schedule_request_period_reset;
+
+/// Fresh installs start at the latest schema, so no migration ever runs on them
+#[ic_cdk::init]
+fn init(config: Option<BackendConfig>) {
+    set_schema_version(latest_schema_version());
+    set_config(validated(config.unwrap_or_default()));
+    schedule_rng_seeding();
+    schedule_trash_purge();
+    schedule_request_period_reset();
+    schedule_subscription_renewals();
+}
+
+/// Bring stable state up to the schema this wasm expects. A config passed on upgrade
+/// replaces the stored one; without it the stored config is kept.
+#[ic_cdk::post_upgrade]
+fn post_upgrade(config: Option<BackendConfig>) {
+    // Must happen before the memory manager claims stable memory
+    capture_legacy_state();
+    // Migrations convert amounts with the configured ledger's decimals
+    if let Some(config) = config {
+        set_config(validated(config));
+    }
+    run_pending_migrations();
+    schedule_rng_seeding();
+    schedule_trash_purge();
+    schedule_request_period_reset();
+    schedule_subscription_renewals();
+    schedule_free_package_backfill();
+}
+
+fn validated(config: BackendConfig) -> BackendConfig {
+    if let Err(err) = validate_config(&config) {
+        ic_cdk::trap(&format!("Invalid config: {:?}", err));
+    }
+    config
+}
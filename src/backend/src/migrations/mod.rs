@@ -0,0 +1,111 @@
+use models::MigrationInfo;
+use stores::{set_schema_version, stored_schema_version};
+
+pub mod models;
+pub mod queries;
+pub mod stores;
+pub mod v1_import_stable_save;
+pub mod v2_numeric_timestamps;
+pub mod v3_storage_usage;
+pub mod v4_token_amounts;
+pub mod v5_storage_kb;
+pub mod v6_client_indexes;
+
+/// A single step that brings stable state from `version - 1` to `version`
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub run: fn(),
+}
+
+/// Every schema migration, in the order they must be applied. Append new entries with
+/// the next version number; never reorder or remove existing ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Import state serialized with stable_save into stable structures",
+        run: v1_import_stable_save::migrate,
+    },
+    Migration {
+        version: 2,
+        description: "Store folder, asset and profile timestamps as nanosecond integers",
+        run: v2_numeric_timestamps::migrate,
+    },
+    Migration {
+        version: 3,
+        description: "Compute per-client storage usage from existing assets",
+        run: v3_storage_usage::migrate,
+    },
+    Migration {
+        version: 4,
+        description: "Price packages and record charges in base units of a named token",
+        run: v4_token_amounts::migrate,
+    },
+    Migration {
+        version: 5,
+        description: "Recount storage usage in whole KB, replacing the fractional MB totals",
+        run: v5_storage_kb::migrate,
+    },
+    Migration {
+        version: 6,
+        description: "Index invoices and subscription events by client",
+        run: v6_client_indexes::migrate,
+    },
+];
+
+impl From<&Migration> for MigrationInfo {
+    fn from(migration: &Migration) -> Self {
+        MigrationInfo {
+            version: migration.version,
+            description: migration.description.to_string(),
+        }
+    }
+}
+
+/// Schema version produced by running every registered migration
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Migrations that still have to run for state stored at `from_version`
+pub fn pending_migrations(from_version: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(move |migration| migration.version > from_version)
+}
+
+/// Applies pending migrations in order, recording the version reached after each step.
+/// A trapping migration aborts the whole upgrade, leaving the previous wasm and state.
+pub fn run_pending_migrations() {
+    let stored_version = stored_schema_version();
+    let latest_version = latest_schema_version();
+
+    if stored_version > latest_version {
+        ic_cdk::trap(&format!(
+            "Stable state is at schema version {} but this wasm only supports up to {}",
+            stored_version, latest_version
+        ));
+    }
+
+    for migration in pending_migrations(stored_version) {
+        ic_cdk::println!(
+            "Running migration {}: {}",
+            migration.version,
+            migration.description
+        );
+        (migration.run)();
+        set_schema_version(migration.version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_numbered_sequentially() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use candid::CandidType;
+use serde::Deserialize;
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct MigrationInfo {
+    pub version: u32,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SchemaInfo {
+    pub stored_version: u32,
+    pub code_version: u32,
+    pub pending_migrations: Vec<MigrationInfo>,
+}
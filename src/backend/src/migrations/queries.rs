@@ -0,0 +1,30 @@
+use ic_cdk::query;
+
+use super::models::{MigrationInfo, SchemaInfo};
+use super::stores::stored_schema_version;
+use super::{latest_schema_version, pending_migrations};
+
+/// Reports the schema version in stable memory and the one this wasm expects
+#[query]
+fn schema_info() -> SchemaInfo {
+    let stored_version = stored_schema_version();
+
+    SchemaInfo {
+        stored_version,
+        code_version: latest_schema_version(),
+        pending_migrations: pending_migrations(stored_version)
+            .map(MigrationInfo::from)
+            .collect(),
+    }
+}
+
+/// Dry run of an upgrade: lists the migrations this wasm would apply, in order, to state
+/// at `from_version` (defaults to the stored version). Call it on the pending wasm with
+/// the `stored_version` reported by the live canister to preview the upgrade.
+#[query]
+fn migration_plan(from_version: Option<u32>) -> Vec<MigrationInfo> {
+    let from_version = from_version.unwrap_or_else(stored_schema_version);
+    pending_migrations(from_version)
+        .map(MigrationInfo::from)
+        .collect()
+}
@@ -0,0 +1,27 @@
+use std::cell::RefCell;
+
+use ic_stable_structures::StableCell;
+
+use crate::common::memory::{Memory, SCHEMA_VERSION_MEMORY_ID, get_memory};
+
+thread_local! {
+    /// Schema version of the data held in stable memory. Canisters that predate
+    /// versioning start at 0.
+    pub static SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(get_memory(SCHEMA_VERSION_MEMORY_ID), 0)
+            .expect("Failed to initialize schema version")
+    );
+}
+
+pub fn stored_schema_version() -> u32 {
+    SCHEMA_VERSION.with(|version| *version.borrow().get())
+}
+
+pub fn set_schema_version(new_version: u32) {
+    SCHEMA_VERSION.with(|version| {
+        version
+            .borrow_mut()
+            .set(new_version)
+            .expect("Failed to store schema version");
+    });
+}
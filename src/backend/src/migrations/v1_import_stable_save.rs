@@ -0,0 +1,77 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use candid::Principal;
+use ic_cdk::api::stable::{stable_read, stable_size};
+use ic_cdk::storage;
+
+use super::v2_numeric_timestamps::LegacyProfile;
+use super::v4_token_amounts::{LegacyClientPackageSubscription, LegacySubscriptionPackage};
+use crate::users::models::Client;
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES, USERS};
+
+/// State layout written by `stable_save` before the stores moved to stable memory
+type LegacyState = (
+    HashMap<Principal, LegacyProfile>,
+    HashMap<Principal, Client>,
+    HashMap<String, LegacySubscriptionPackage>,
+    HashMap<String, LegacyClientPackageSubscription>,
+);
+
+/// Magic bytes written by the memory manager at the start of stable memory
+const MEMORY_MANAGER_MAGIC: &[u8; 3] = b"MGR";
+
+thread_local! {
+    static LEGACY_STATE: RefCell<Option<LegacyState>> = const { RefCell::new(None) };
+}
+
+/// Decodes state left by `stable_save`, if stable memory has not been claimed by the
+/// memory manager yet. Must run before any stable store is touched, since initializing
+/// the memory manager overwrites the serialized state.
+pub fn capture_legacy_state() {
+    if stable_size() == 0 {
+        return;
+    }
+
+    let mut magic = [0; 3];
+    stable_read(0, &mut magic);
+    if &magic == MEMORY_MANAGER_MAGIC {
+        return;
+    }
+
+    let state: LegacyState = storage::stable_restore().expect("Failed to restore state");
+    LEGACY_STATE.with(|legacy| *legacy.borrow_mut() = Some(state));
+}
+
+/// Copies the captured `stable_save` state into the stable stores
+pub fn migrate() {
+    let Some((users, clients, subscription_packages, client_subscriptions)) =
+        LEGACY_STATE.with(|legacy| legacy.borrow_mut().take())
+    else {
+        return;
+    };
+
+    USERS.with(|state| {
+        let mut state = state.borrow_mut();
+        for (key, value) in users {
+            state.insert(key, value.into());
+        }
+    });
+    CLIENTS.with(|state| {
+        let mut state = state.borrow_mut();
+        for (key, value) in clients {
+            state.insert(key, value);
+        }
+    });
+    SUBSCRIPTION_PACKAGES.with(|state| {
+        let mut state = state.borrow_mut();
+        for (key, value) in subscription_packages {
+            state.insert(key, value.into());
+        }
+    });
+    CLIENT_SUBSCRIPTIONS.with(|state| {
+        let mut state = state.borrow_mut();
+        for (key, value) in client_subscriptions {
+            state.insert(key, value.into());
+        }
+    });
+}
@@ -0,0 +1,147 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+use crate::assets::models::{Asset, Folder};
+use crate::assets::stores::{ASSETS, FOLDERS};
+use crate::users::models::Profile;
+use crate::users::stores::USERS;
+
+/// `Folder` as stored before timestamps became nanosecond integers
+#[derive(CandidType, Deserialize)]
+pub struct LegacyFolder {
+    pub uuid: String,
+    pub name: String,
+    pub description: String,
+    pub client_id: String,
+    pub owner_id: Principal,
+    pub date_added: String,
+    pub last_updated: String,
+}
+
+/// `Asset` as stored before timestamps became nanosecond integers
+#[derive(CandidType, Deserialize)]
+pub struct LegacyAsset {
+    pub uuid: String,
+    pub name: String,
+    pub description: String,
+    pub folder_uuid: String,
+    pub ipfs_hash: String,
+    pub size_mb: f64,
+    pub owner_id: Principal,
+    pub date_added: String,
+    pub last_updated: String,
+}
+
+/// `Profile` as stored before timestamps became nanosecond integers
+#[derive(CandidType, Deserialize)]
+pub struct LegacyProfile {
+    pub principal: Principal,
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub image_hash: Option<String>,
+    pub date_added: String,
+    pub last_updated: String,
+}
+
+/// Legacy timestamps are `time().to_string()`, so they parse straight back
+fn parse_timestamp(timestamp: &str) -> u64 {
+    timestamp.parse().unwrap_or_default()
+}
+
+impl From<LegacyFolder> for Folder {
+    fn from(folder: LegacyFolder) -> Self {
+        Folder {
+            uuid: folder.uuid,
+            name: folder.name,
+            description: folder.description,
+            client_id: folder.client_id,
+            owner_id: folder.owner_id,
+            date_added: parse_timestamp(&folder.date_added),
+            last_updated: parse_timestamp(&folder.last_updated),
+            parent_uuid: None,
+        }
+    }
+}
+
+impl From<LegacyAsset> for Asset {
+    fn from(asset: LegacyAsset) -> Self {
+        Asset {
+            uuid: asset.uuid,
+            name: asset.name,
+            description: asset.description,
+            folder_uuid: asset.folder_uuid,
+            ipfs_hash: asset.ipfs_hash,
+            size_mb: asset.size_mb,
+            owner_id: asset.owner_id,
+            date_added: parse_timestamp(&asset.date_added),
+            last_updated: parse_timestamp(&asset.last_updated),
+            copied_from: None,
+        }
+    }
+}
+
+impl From<LegacyProfile> for Profile {
+    fn from(profile: LegacyProfile) -> Self {
+        Profile {
+            principal: profile.principal,
+            email: profile.email,
+            first_name: profile.first_name,
+            last_name: profile.last_name,
+            image_hash: profile.image_hash,
+            date_added: parse_timestamp(&profile.date_added),
+            last_updated: parse_timestamp(&profile.last_updated),
+        }
+    }
+}
+
+/// Rewrites every folder, asset and profile so none is left in the string layout.
+/// Reading goes through the legacy fallback in their `Storable` impls.
+pub fn migrate() {
+    FOLDERS.with(|folders| {
+        let mut folders = folders.borrow_mut();
+        let entries: Vec<_> = folders.iter().collect();
+        for (uuid, folder) in entries {
+            folders.insert(uuid, folder);
+        }
+    });
+    ASSETS.with(|assets| {
+        let mut assets = assets.borrow_mut();
+        let entries: Vec<_> = assets.iter().collect();
+        for (uuid, asset) in entries {
+            assets.insert(uuid, asset);
+        }
+    });
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let entries: Vec<_> = users.iter().collect();
+        for (principal, profile) in entries {
+            users.insert(principal, profile);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::Storable;
+    use std::borrow::Cow;
+
+    #[test]
+    fn decodes_string_timestamps() {
+        let legacy = LegacyFolder {
+            uuid: "1".to_string(),
+            name: "2026".to_string(),
+            description: String::new(),
+            client_id: Principal::anonymous().to_string(),
+            owner_id: Principal::anonymous(),
+            date_added: "1709210096123456789".to_string(),
+            last_updated: "1709210096123456790".to_string(),
+        };
+        let bytes = candid::encode_one(&legacy).unwrap();
+
+        let folder = Folder::from_bytes(Cow::Owned(bytes));
+        assert_eq!(folder.date_added, 1_709_210_096_123_456_789);
+        assert_eq!(folder.last_updated, 1_709_210_096_123_456_790);
+    }
+}
@@ -0,0 +1,31 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::assets::stores::ASSETS;
+use crate::common::memory::{LEGACY_STORAGE_USAGE_MEMORY_ID, Memory, get_memory};
+
+thread_local! {
+    /// Storage usage in fractional MB, as kept until migration 5 recounted it in KB
+    pub static LEGACY_STORAGE_USAGE: RefCell<StableBTreeMap<Principal, f64, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LEGACY_STORAGE_USAGE_MEMORY_ID)));
+}
+
+/// Seeds the storage usage counters from the assets that existed before quotas
+pub fn migrate() {
+    let mut totals = HashMap::new();
+    ASSETS.with(|assets| {
+        for asset in assets.borrow().values() {
+            *totals.entry(asset.owner_id).or_insert(0.0) += asset.size_mb;
+        }
+    });
+
+    LEGACY_STORAGE_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        for (owner, used_mb) in totals {
+            usage.insert(owner, used_mb);
+        }
+    });
+}
@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+
+use candid::{CandidType, Nat, Principal};
+use ic_stable_structures::StableBTreeMap;
+use serde::Deserialize;
+
+use crate::common::memory::{LEGACY_PREPAID_BALANCES_MEMORY_ID, Memory, get_memory};
+use crate::promotions::models::{Discount, PromoCode, PromoRedemption};
+use crate::promotions::stores::{PROMO_CODES, PROMO_REDEMPTIONS};
+use crate::subscriptions::models::{Invoice, InvoiceStatus};
+use crate::subscriptions::stores::INVOICES;
+use crate::transactions::models::{TokenAmount, TokenDescriptor};
+use crate::transactions::stores::PREPAID_BALANCES;
+use crate::users::models::{ClientPackageSubscription, SubscriptionPackage, SubscriptionState};
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, SUBSCRIPTION_PACKAGES};
+
+thread_local! {
+    /// Prepaid e8s of each client on the default ledger, as kept before multi-token
+    /// support
+    static LEGACY_PREPAID_BALANCES: RefCell<StableBTreeMap<Principal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LEGACY_PREPAID_BALANCES_MEMORY_ID)));
+}
+
+/// `SubscriptionPackage` as stored before prices became per-token base units
+#[derive(CandidType, Deserialize)]
+pub struct LegacySubscriptionPackage {
+    pub uuid: String,
+    pub name: String,
+    pub price: f64,
+    pub storage_capacity_mb: u64,
+    pub monthly_requests: u64,
+    pub max_allowed_sessions: u64,
+    pub last_updated: u64,
+}
+
+/// `ClientPackageSubscription` as stored before amounts became per-token base units
+#[derive(CandidType, Deserialize)]
+pub struct LegacyClientPackageSubscription {
+    pub client_uuid: String,
+    pub subscription_package_uuid: String,
+    pub amount: f64,
+    pub expires_at: u64,
+    pub block_index: Option<u64>,
+    pub period_start: Option<u64>,
+    pub auto_renew: Option<bool>,
+    pub state: Option<SubscriptionState>,
+    pub pending_package_uuid: Option<String>,
+    pub trial: Option<bool>,
+}
+
+/// `Invoice` as stored before amounts became per-token base units
+#[derive(CandidType, Deserialize)]
+pub struct LegacyInvoice {
+    pub number: u64,
+    pub client_principal: Principal,
+    pub client_uuid: String,
+    pub subscription_package_uuid: String,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub amount: u64,
+    pub token: Principal,
+    pub block_index: Option<u64>,
+    pub status: InvoiceStatus,
+    pub issued_at: u64,
+    pub promo_code: Option<String>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub enum LegacyDiscount {
+    Percentage(u8),
+    Fixed(u64),
+}
+
+/// `PromoCode` as stored before fixed discounts named their token
+#[derive(CandidType, Deserialize)]
+pub struct LegacyPromoCode {
+    pub code: String,
+    pub discount: LegacyDiscount,
+    pub expires_at: u64,
+    pub max_redemptions: u64,
+    pub redemptions: u64,
+    pub package_uuids: Option<Vec<String>>,
+    pub last_updated: u64,
+}
+
+/// `PromoRedemption` as stored before amounts became per-token base units
+#[derive(CandidType, Deserialize)]
+pub struct LegacyPromoRedemption {
+    pub code: String,
+    pub client_principal: Principal,
+    pub subscription_package_uuid: String,
+    pub discount_amount: u64,
+    pub redeemed_at: u64,
+}
+
+/// Legacy prices were whole tokens of the default ledger
+fn price_to_base_units(price: f64) -> Nat {
+    let scale = 10f64.powi(TokenDescriptor::default_token().decimals.into());
+    Nat::from((price * scale).round() as u64)
+}
+
+fn default_amount(amount: impl Into<Nat>) -> TokenAmount {
+    TokenAmount {
+        token: TokenDescriptor::default_token(),
+        amount: amount.into(),
+    }
+}
+
+impl From<LegacySubscriptionPackage> for SubscriptionPackage {
+    fn from(package: LegacySubscriptionPackage) -> Self {
+        let price = price_to_base_units(package.price);
+        SubscriptionPackage {
+            uuid: package.uuid,
+            name: package.name,
+            prices: vec![default_amount(price)],
+            storage_capacity_mb: package.storage_capacity_mb,
+            monthly_requests: package.monthly_requests,
+            max_allowed_sessions: package.max_allowed_sessions,
+            last_updated: package.last_updated,
+        }
+    }
+}
+
+impl From<LegacyClientPackageSubscription> for ClientPackageSubscription {
+    fn from(subscription: LegacyClientPackageSubscription) -> Self {
+        ClientPackageSubscription {
+            client_uuid: subscription.client_uuid,
+            subscription_package_uuid: subscription.subscription_package_uuid,
+            amount: price_to_base_units(subscription.amount),
+            token: TokenDescriptor::default_token(),
+            expires_at: subscription.expires_at,
+            block_index: subscription.block_index,
+            period_start: subscription.period_start,
+            auto_renew: subscription.auto_renew,
+            state: subscription.state,
+            pending_package_uuid: subscription.pending_package_uuid,
+            trial: subscription.trial,
+        }
+    }
+}
+
+impl From<LegacyInvoice> for Invoice {
+    fn from(invoice: LegacyInvoice) -> Self {
+        Invoice {
+            number: invoice.number,
+            client_principal: invoice.client_principal,
+            client_uuid: invoice.client_uuid,
+            subscription_package_uuid: invoice.subscription_package_uuid,
+            period_start: invoice.period_start,
+            period_end: invoice.period_end,
+            amount: Nat::from(invoice.amount),
+            token: TokenDescriptor {
+                ledger_canister_id: invoice.token,
+                ..TokenDescriptor::default_token()
+            },
+            block_index: invoice.block_index,
+            status: invoice.status,
+            issued_at: invoice.issued_at,
+            promo_code: invoice.promo_code,
+        }
+    }
+}
+
+impl From<LegacyPromoCode> for PromoCode {
+    fn from(promo: LegacyPromoCode) -> Self {
+        PromoCode {
+            code: promo.code,
+            discount: match promo.discount {
+                LegacyDiscount::Percentage(percent) => Discount::Percentage(percent),
+                LegacyDiscount::Fixed(amount) => Discount::Fixed(default_amount(amount)),
+            },
+            expires_at: promo.expires_at,
+            max_redemptions: promo.max_redemptions,
+            redemptions: promo.redemptions,
+            package_uuids: promo.package_uuids,
+            last_updated: promo.last_updated,
+        }
+    }
+}
+
+impl From<LegacyPromoRedemption> for PromoRedemption {
+    fn from(redemption: LegacyPromoRedemption) -> Self {
+        PromoRedemption {
+            code: redemption.code,
+            client_principal: redemption.client_principal,
+            subscription_package_uuid: redemption.subscription_package_uuid,
+            discount_amount: default_amount(redemption.discount_amount),
+            redeemed_at: redemption.redeemed_at,
+        }
+    }
+}
+
+/// Rewrites packages, subscriptions, invoices and promotions with amounts in per-token
+/// base units, and moves prepaid balances to the default ledger's entry in the
+/// per-token balance map. Reading goes through the legacy fallback in their `Storable`
+/// impls.
+pub fn migrate() {
+    SUBSCRIPTION_PACKAGES.with(|packages| {
+        let mut packages = packages.borrow_mut();
+        let entries: Vec<_> = packages.iter().collect();
+        for (uuid, package) in entries {
+            packages.insert(uuid, package);
+        }
+    });
+    CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let entries: Vec<_> = subscriptions.iter().collect();
+        for (client_uuid, subscription) in entries {
+            subscriptions.insert(client_uuid, subscription);
+        }
+    });
+    INVOICES.with(|invoices| {
+        let mut invoices = invoices.borrow_mut();
+        let entries: Vec<_> = invoices.iter().collect();
+        for (number, invoice) in entries {
+            invoices.insert(number, invoice);
+        }
+    });
+    PROMO_CODES.with(|codes| {
+        let mut codes = codes.borrow_mut();
+        let entries: Vec<_> = codes.iter().collect();
+        for (code, promo) in entries {
+            codes.insert(code, promo);
+        }
+    });
+    PROMO_REDEMPTIONS.with(|redemptions| {
+        let mut redemptions = redemptions.borrow_mut();
+        let entries: Vec<_> = redemptions.iter().collect();
+        for (sequence, redemption) in entries {
+            redemptions.insert(sequence, redemption);
+        }
+    });
+
+    let legacy_balances: Vec<_> = LEGACY_PREPAID_BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let entries: Vec<_> = balances.iter().collect();
+        for (principal, _) in &entries {
+            balances.remove(principal);
+        }
+        entries
+    });
+    PREPAID_BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        for (principal, balance) in legacy_balances {
+            let balance = default_amount(balance);
+            balances.insert((principal, balance.token.ledger_canister_id), balance);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::Storable;
+    use std::borrow::Cow;
+
+    #[test]
+    fn decodes_whole_token_prices() {
+        let legacy = LegacySubscriptionPackage {
+            uuid: "1".to_string(),
+            name: "Pro".to_string(),
+            price: 1.25,
+            storage_capacity_mb: 1_024,
+            monthly_requests: 10_000,
+            max_allowed_sessions: 3,
+            last_updated: 0,
+        };
+        let bytes = candid::encode_one(&legacy).unwrap();
+
+        let package = SubscriptionPackage::from_bytes(Cow::Owned(bytes));
+        assert_eq!(package.prices.len(), 1);
+        assert_eq!(package.prices[0].amount, 125_000_000u64);
+        assert_eq!(package.prices[0].token, TokenDescriptor::default_token());
+    }
+}
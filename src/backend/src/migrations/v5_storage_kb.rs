@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use crate::assets::stores::ASSETS;
+use crate::usage::stores::{STORAGE_USAGE, size_kb};
+
+use super::v3_storage_usage::LEGACY_STORAGE_USAGE;
+
+/// Recounts each owner's live assets in whole KB, the unit every later change is
+/// accounted in, and drops the fractional MB totals this replaces
+pub fn migrate() {
+    let mut totals = HashMap::new();
+    ASSETS.with(|assets| {
+        for asset in assets.borrow().values() {
+            *totals.entry(asset.owner_id).or_insert(0) += size_kb(asset.size_mb);
+        }
+    });
+
+    STORAGE_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        for (owner, used_kb) in totals {
+            usage.insert(owner, used_kb);
+        }
+    });
+    LEGACY_STORAGE_USAGE.with(|usage| usage.borrow_mut().clear_new());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::models::Asset;
+    use crate::usage::stores::storage_used_kb;
+    use candid::Principal;
+
+    #[test]
+    fn recounts_in_kb_and_drops_the_mb_totals() {
+        let owner = Principal::from_slice(&[7]);
+        for (uuid, size_mb) in [("a", 1.0), ("b", 0.0001)] {
+            let asset = Asset {
+                uuid: uuid.to_string(),
+                name: uuid.to_string(),
+                description: String::new(),
+                folder_uuid: "folder".to_string(),
+                ipfs_hash: String::new(),
+                size_mb,
+                owner_id: owner,
+                date_added: 0,
+                last_updated: 0,
+                copied_from: None,
+            };
+            ASSETS.with(|assets| assets.borrow_mut().insert(uuid.to_string(), asset));
+        }
+        LEGACY_STORAGE_USAGE.with(|usage| usage.borrow_mut().insert(owner, 1.0001));
+
+        migrate();
+
+        assert_eq!(storage_used_kb(&owner), 1_025);
+        assert!(LEGACY_STORAGE_USAGE.with(|usage| usage.borrow().is_empty()));
+    }
+}
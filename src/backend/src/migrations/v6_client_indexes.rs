@@ -0,0 +1,33 @@
+use crate::subscriptions::stores::{CLIENT_EVENTS, CLIENT_INVOICES, INVOICES, SUBSCRIPTION_EVENTS};
+
+/// Indexes the existing invoices and subscription events by client, so per-client
+/// lookups stop scanning both stores
+pub fn migrate() {
+    let invoices: Vec<_> = INVOICES.with(|invoices| {
+        invoices
+            .borrow()
+            .iter()
+            .map(|(number, invoice)| (invoice.client_principal, number))
+            .collect()
+    });
+    CLIENT_INVOICES.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in invoices {
+            index.insert(key, ());
+        }
+    });
+
+    let events: Vec<_> = SUBSCRIPTION_EVENTS.with(|events| {
+        events
+            .borrow()
+            .iter()
+            .map(|(sequence, event)| (event.client_principal, sequence))
+            .collect()
+    });
+    CLIENT_EVENTS.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in events {
+            index.insert(key, ());
+        }
+    });
+}
@@ -0,0 +1,43 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+use crate::common::memory::impl_candid_storable;
+use crate::migrations::v4_token_amounts::{LegacyPromoCode, LegacyPromoRedemption};
+use crate::transactions::models::TokenAmount;
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum Discount {
+    /// Percentage off the package price, 1 to 100
+    Percentage(u8),
+    /// Amount off the package price, only redeemable when paying in its token
+    Fixed(TokenAmount),
+}
+
+/// A discount code for the first term of a subscription
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PromoCode {
+    /// Upper-case code, as typed by clients in any case
+    pub code: String,
+    pub discount: Discount,
+    pub expires_at: u64,
+    pub max_redemptions: u64,
+    pub redemptions: u64,
+    /// Packages the code applies to, any package when `None`
+    pub package_uuids: Option<Vec<String>>,
+    pub last_updated: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PromoRedemption {
+    pub code: String,
+    pub client_principal: Principal,
+    pub subscription_package_uuid: String,
+    /// Amount taken off the price, in the token paid with
+    pub discount_amount: TokenAmount,
+    pub redeemed_at: u64,
+}
+
+impl_candid_storable!(
+    PromoCode | LegacyPromoCode,
+    PromoRedemption | LegacyPromoRedemption
+);
@@ -0,0 +1,79 @@
+use ic_cdk::api::time;
+use ic_cdk::update;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::roles::{guards::require_role, models::Role};
+use crate::users::stores::SUBSCRIPTION_PACKAGES;
+
+use super::models::{Discount, PromoCode};
+use super::stores::{PROMO_CODES, normalize_code};
+
+const MAX_CODE_LENGTH: usize = 32;
+
+/// Creates or updates a promotion code (admins only). Updating keeps the redemption
+/// count, so lowering `max_redemptions` below it closes the code.
+#[update]
+fn create_update_promo_code(
+    code: String,
+    discount: Discount,
+    expires_at: u64,
+    max_redemptions: u64,
+    package_uuids: Option<Vec<String>>,
+) -> BackendResult<PromoCode> {
+    require_role(Role::Admin)?;
+
+    let code = normalize_code(&code);
+    let valid_code = !code.is_empty()
+        && code.len() <= MAX_CODE_LENGTH
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_code {
+        return Err(BackendError::validation(
+            "code",
+            format!(
+                "must be 1 to {} letters, digits, '-' or '_'",
+                MAX_CODE_LENGTH
+            ),
+        ));
+    }
+    match &discount {
+        Discount::Percentage(percent) if *percent == 0 || *percent > 100 => {
+            return Err(BackendError::validation(
+                "discount",
+                "percentage must be between 1 and 100",
+            ));
+        }
+        Discount::Fixed(off) if off.amount == 0u64 => {
+            return Err(BackendError::validation(
+                "discount",
+                "fixed amount must be greater than zero",
+            ));
+        }
+        _ => {}
+    }
+    for package_uuid in package_uuids.iter().flatten() {
+        if !SUBSCRIPTION_PACKAGES.with(|packages| packages.borrow().contains_key(package_uuid)) {
+            return Err(BackendError::not_found(
+                "subscription_package",
+                package_uuid,
+            ));
+        }
+    }
+
+    PROMO_CODES.with(|codes| {
+        let mut codes = codes.borrow_mut();
+        let redemptions = codes.get(&code).map_or(0, |promo| promo.redemptions);
+        let promo = PromoCode {
+            code: code.clone(),
+            discount,
+            expires_at,
+            max_redemptions,
+            redemptions,
+            package_uuids,
+            last_updated: time(),
+        };
+        codes.insert(code, promo.clone());
+        Ok(promo)
+    })
+}
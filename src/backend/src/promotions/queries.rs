@@ -0,0 +1,30 @@
+use ic_cdk::query;
+
+use crate::common::errors::BackendResult;
+use crate::roles::{guards::require_role, models::Role};
+
+use super::models::{PromoCode, PromoRedemption};
+use super::stores::{PROMO_CODES, PROMO_REDEMPTIONS, normalize_code};
+
+/// Lists every promotion code (admins only)
+#[query]
+fn promo_codes() -> BackendResult<Vec<PromoCode>> {
+    require_role(Role::Admin)?;
+
+    Ok(PROMO_CODES.with(|codes| codes.borrow().values().collect()))
+}
+
+/// Redemptions of `code`, oldest first (admins only)
+#[query]
+fn promo_code_redemptions(code: String) -> BackendResult<Vec<PromoRedemption>> {
+    require_role(Role::Admin)?;
+
+    let code = normalize_code(&code);
+    Ok(PROMO_REDEMPTIONS.with(|redemptions| {
+        redemptions
+            .borrow()
+            .values()
+            .filter(|redemption| redemption.code == code)
+            .collect()
+    }))
+}
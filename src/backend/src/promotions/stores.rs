@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+
+use candid::{Nat, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::{
+    Memory, PROMO_CODES_MEMORY_ID, PROMO_REDEMPTIONS_MEMORY_ID, get_memory,
+};
+
+use crate::transactions::models::TokenAmount;
+
+use super::models::{Discount, PromoCode, PromoRedemption};
+
+thread_local! {
+    /// Promotion codes, keyed by upper-case code
+    pub static PROMO_CODES: RefCell<StableBTreeMap<String, PromoCode, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(PROMO_CODES_MEMORY_ID)));
+    /// Append-only redemption log, keyed by sequence number
+    pub static PROMO_REDEMPTIONS: RefCell<StableBTreeMap<u64, PromoRedemption, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(PROMO_REDEMPTIONS_MEMORY_ID)));
+}
+
+/// Codes are matched case-insensitively
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// `price` after `discount`, never below zero. Fixed discounts in another token than
+/// the price's take nothing off.
+pub fn apply_discount(price: &TokenAmount, discount: &Discount) -> Nat {
+    match discount {
+        Discount::Percentage(percent) => {
+            price.amount.clone() * Nat::from(100 - (*percent).min(100)) / Nat::from(100u8)
+        }
+        Discount::Fixed(off) if off.token.ledger_canister_id != price.token.ledger_canister_id => {
+            price.amount.clone()
+        }
+        Discount::Fixed(off) if off.amount >= price.amount => Nat::from(0u8),
+        Discount::Fixed(off) => price.amount.clone() - off.amount.clone(),
+    }
+}
+
+/// Checks that `code` can be redeemed by `principal` for `package_uuid` paid in
+/// `price`'s token and counts the redemption against its limit. Callers must
+/// `release_promo_code` if the purchase then fails, or `record_redemption` once it
+/// succeeded.
+pub fn reserve_promo_code(
+    code: &str,
+    principal: &Principal,
+    package_uuid: &str,
+    price: &TokenAmount,
+) -> BackendResult<PromoCode> {
+    let code = normalize_code(code);
+    let already_redeemed = PROMO_REDEMPTIONS.with(|redemptions| {
+        redemptions
+            .borrow()
+            .values()
+            .any(|redemption| redemption.code == code && redemption.client_principal == *principal)
+    });
+    if already_redeemed {
+        return Err(BackendError::already_exists("promo_redemption", &code));
+    }
+
+    PROMO_CODES.with(|codes| {
+        let mut codes = codes.borrow_mut();
+        let mut promo = codes
+            .get(&code)
+            .ok_or_else(|| BackendError::not_found("promo_code", &code))?;
+        if promo.expires_at <= time() {
+            return Err(BackendError::validation("promo_code", "has expired"));
+        }
+        if promo.redemptions >= promo.max_redemptions {
+            return Err(BackendError::QuotaExceeded {
+                resource: "promo_redemptions".to_string(),
+                limit: promo.max_redemptions,
+            });
+        }
+        let applies = promo
+            .package_uuids
+            .as_ref()
+            .is_none_or(|uuids| uuids.iter().any(|uuid| uuid == package_uuid));
+        if !applies {
+            return Err(BackendError::validation(
+                "promo_code",
+                "does not apply to this package",
+            ));
+        }
+        if let Discount::Fixed(off) = &promo.discount
+            && off.token.ledger_canister_id != price.token.ledger_canister_id
+        {
+            return Err(BackendError::validation(
+                "promo_code",
+                format!("only applies to payments in {}", off.token.symbol),
+            ));
+        }
+
+        promo.redemptions += 1;
+        codes.insert(code, promo.clone());
+        Ok(promo)
+    })
+}
+
+/// Returns a reservation made by `reserve_promo_code`
+pub fn release_promo_code(code: &str) {
+    PROMO_CODES.with(|codes| {
+        let mut codes = codes.borrow_mut();
+        if let Some(mut promo) = codes.get(&code.to_string()) {
+            promo.redemptions = promo.redemptions.saturating_sub(1);
+            codes.insert(code.to_string(), promo);
+        }
+    });
+}
+
+pub fn record_redemption(redemption: PromoRedemption) {
+    PROMO_REDEMPTIONS.with(|redemptions| {
+        let mut redemptions = redemptions.borrow_mut();
+        let sequence = redemptions
+            .last_key_value()
+            .map_or(0, |(sequence, _)| sequence + 1);
+        redemptions.insert(sequence, redemption);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::transactions::models::TokenDescriptor;
+
+    fn amount(ledger: Principal, amount: u64) -> TokenAmount {
+        TokenAmount {
+            token: TokenDescriptor {
+                ledger_canister_id: ledger,
+                symbol: "TKN".to_string(),
+                decimals: 8,
+            },
+            amount: Nat::from(amount),
+        }
+    }
+
+    #[test]
+    fn discounts_never_go_below_zero() {
+        let ledger = Principal::anonymous();
+        let percentage =
+            |price, percent| apply_discount(&amount(ledger, price), &Discount::Percentage(percent));
+        let fixed = |price, off| {
+            apply_discount(
+                &amount(ledger, price),
+                &Discount::Fixed(amount(ledger, off)),
+            )
+        };
+        assert_eq!(percentage(1_000, 25), 750u64);
+        assert_eq!(percentage(1_000, 100), 0u64);
+        assert_eq!(percentage(999, 50), 499u64);
+        assert_eq!(fixed(1_000, 300), 700u64);
+        assert_eq!(fixed(1_000, 5_000), 0u64);
+    }
+
+    #[test]
+    fn fixed_discounts_only_apply_in_their_token() {
+        let price = amount(Principal::anonymous(), 1_000);
+        let off = amount(Principal::management_canister(), 300);
+        assert_eq!(apply_discount(&price, &Discount::Fixed(off)), 1_000u64);
+    }
+}
@@ -0,0 +1,30 @@
+use candid::Principal;
+use ic_cdk::api::is_controller;
+use ic_cdk::caller;
+
+use crate::common::errors::{BackendError, BackendResult};
+
+use super::models::Role;
+use super::stores::ROLES;
+
+/// Resolves the effective role of `principal`
+pub fn role_of(principal: &Principal) -> Role {
+    if *principal == Principal::anonymous() {
+        return Role::Client;
+    }
+    if is_controller(principal) {
+        return Role::Owner;
+    }
+    ROLES.with(|roles| roles.borrow().get(principal).unwrap_or(Role::Client))
+}
+
+/// Guard for privileged endpoints: fails with `Unauthorized` unless the caller holds
+/// at least `minimum`, and returns the caller's role otherwise
+pub fn require_role(minimum: Role) -> BackendResult<Role> {
+    let role = role_of(&caller());
+    if role >= minimum {
+        Ok(role)
+    } else {
+        Err(BackendError::Unauthorized)
+    }
+}
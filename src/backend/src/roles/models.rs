@@ -0,0 +1,22 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+use crate::common::memory::impl_candid_storable;
+
+/// Roles in ascending order of privilege. Every principal without an explicit
+/// assignment is a `Client`, and canister controllers are always `Owner`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize)]
+pub enum Role {
+    Client,
+    Support,
+    Admin,
+    Owner,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+impl_candid_storable!(Role);
@@ -0,0 +1,53 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::common::errors::{BackendError, BackendResult};
+
+use super::guards::{require_role, role_of};
+use super::models::{Role, RoleAssignment};
+use super::stores::ROLES;
+
+/// Assigns `role` to `principal`. Callers may only hand out roles below their own,
+/// so admins manage support staff and owners manage admins.
+#[update]
+fn grant_role(principal: Principal, role: Role) -> BackendResult<RoleAssignment> {
+    let caller_role = require_role(Role::Admin)?;
+
+    if principal == Principal::anonymous() {
+        return Err(BackendError::validation(
+            "principal",
+            "the anonymous principal cannot hold a role",
+        ));
+    }
+    if role >= caller_role || role_of(&principal) >= caller_role {
+        return Err(BackendError::Unauthorized);
+    }
+
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        if role == Role::Client {
+            roles.remove(&principal);
+        } else {
+            roles.insert(principal, role);
+        }
+    });
+
+    Ok(RoleAssignment { principal, role })
+}
+
+/// Drops any role held by `principal`, making them a plain client again
+#[update]
+fn revoke_role(principal: Principal) -> BackendResult<RoleAssignment> {
+    let caller_role = require_role(Role::Admin)?;
+
+    if role_of(&principal) >= caller_role {
+        return Err(BackendError::Unauthorized);
+    }
+
+    ROLES.with(|roles| roles.borrow_mut().remove(&principal));
+
+    Ok(RoleAssignment {
+        principal,
+        role: Role::Client,
+    })
+}
@@ -0,0 +1,26 @@
+use ic_cdk::{caller, query};
+
+use crate::common::errors::BackendResult;
+
+use super::guards::{require_role, role_of};
+use super::models::{Role, RoleAssignment};
+use super::stores::ROLES;
+
+#[query]
+fn my_role() -> Role {
+    role_of(&caller())
+}
+
+/// Lists explicit role assignments; controllers are owners without being listed
+#[query]
+fn role_assignments() -> BackendResult<Vec<RoleAssignment>> {
+    require_role(Role::Support)?;
+
+    Ok(ROLES.with(|roles| {
+        roles
+            .borrow()
+            .iter()
+            .map(|(principal, role)| RoleAssignment { principal, role })
+            .collect()
+    }))
+}
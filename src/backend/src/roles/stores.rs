@@ -0,0 +1,28 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::memory::{Memory, ROLES_MEMORY_ID, get_memory};
+
+use super::models::Role;
+
+thread_local! {
+    /// Explicit role assignments; principals missing here are clients
+    pub static ROLES: RefCell<StableBTreeMap<Principal, Role, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(ROLES_MEMORY_ID)));
+}
+
+/// Grants `Admin` to each principal, leaving higher assignments untouched
+pub fn seed_admins(admins: &[Principal]) {
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        for admin in admins {
+            if *admin != Principal::anonymous()
+                && roles.get(admin).is_none_or(|role| role < Role::Admin)
+            {
+                roles.insert(*admin, Role::Admin);
+            }
+        }
+    });
+}
@@ -0,0 +1,24 @@
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_cdk::caller;
+
+use super::stores::{SESSIONS, is_usable};
+
+/// The principal a call acts for: the owning client when the caller is one of its
+/// usable sessions, otherwise the caller itself. Sessions over the package's cap thus
+/// act for nobody. Also refreshes the session's last-seen time, which only sticks for
+/// update calls.
+pub fn effective_caller() -> Principal {
+    let caller = caller();
+
+    let Some(mut session) = SESSIONS.with(|sessions| sessions.borrow().get(&caller)) else {
+        return caller;
+    };
+    if !is_usable(&session) {
+        return caller;
+    }
+    let client_principal = session.client_principal;
+    session.last_seen_at = time();
+    SESSIONS.with(|sessions| sessions.borrow_mut().insert(caller, session));
+    client_principal
+}
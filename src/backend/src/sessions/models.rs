@@ -0,0 +1,30 @@
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+use crate::common::memory::impl_candid_storable;
+
+/// A principal, such as a device's Internet Identity delegation, allowed to act for
+/// a client
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Session {
+    pub principal: Principal,
+    /// The client principal this session acts for
+    pub client_principal: Principal,
+    pub label: String,
+    pub created_at: u64,
+    /// Time of the session's last update call
+    pub last_seen_at: u64,
+}
+
+/// A client's offer to let `principal` act for it, pending until `principal` accepts
+/// it with `accept_session`
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SessionInvite {
+    pub principal: Principal,
+    pub client_principal: Principal,
+    pub label: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+impl_candid_storable!(Session, SessionInvite);
@@ -0,0 +1,105 @@
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_cdk::{caller, update};
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::users::guards::require_client;
+use crate::users::stores::active_package;
+
+use super::guards::effective_caller;
+use super::models::{Session, SessionInvite};
+use super::stores::{
+    SESSION_INVITES, SESSIONS, accept_invite, check_session_candidate, client_sessions, is_session,
+};
+
+const MAX_LABEL_LENGTH: usize = 64;
+
+/// How long a session invite can be accepted: 1 day
+const INVITE_TTL_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Invites `principal` to act for the calling client. The session only exists once
+/// `principal` calls `accept_session` itself. Only the client principal can invite,
+/// and only principals that aren't registered users.
+#[update]
+fn register_session(principal: Principal, label: String) -> BackendResult<SessionInvite> {
+    if is_session(&caller()) {
+        return Err(BackendError::Unauthorized);
+    }
+    let client = require_client()?;
+
+    let label = label.trim().to_string();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+        return Err(BackendError::validation(
+            "label",
+            format!("must be 1 to {} characters", MAX_LABEL_LENGTH),
+        ));
+    }
+    if principal == client.principal {
+        return Err(BackendError::validation(
+            "principal",
+            "must be a principal other than the client's own",
+        ));
+    }
+    check_session_candidate(&principal)?;
+
+    let limit = session_limit(&client.principal);
+    if client_sessions(&client.principal).len() as u64 >= limit {
+        return Err(BackendError::QuotaExceeded {
+            resource: "sessions".to_string(),
+            limit,
+        });
+    }
+
+    let now = time();
+    let invite = SessionInvite {
+        principal,
+        client_principal: client.principal,
+        label,
+        created_at: now,
+        expires_at: now + INVITE_TTL_NS,
+    };
+    SESSION_INVITES.with(|invites| {
+        invites
+            .borrow_mut()
+            .insert((principal, client.principal), invite.clone())
+    });
+
+    Ok(invite)
+}
+
+/// Accepts the calling principal's invite from `client_principal`, after which it acts
+/// for that client
+#[update]
+fn accept_session(client_principal: Principal) -> BackendResult<Session> {
+    accept_invite(
+        caller(),
+        client_principal,
+        session_limit(&client_principal),
+        time(),
+    )
+}
+
+fn session_limit(client_principal: &Principal) -> u64 {
+    active_package(client_principal).map_or(0, |package| package.max_allowed_sessions)
+}
+
+/// Removes one of the caller's sessions. A session may also revoke itself, even one
+/// over the package's cap.
+#[update]
+fn revoke_session(principal: Principal) -> BackendResult<Session> {
+    let caller = caller();
+    let client_principal = effective_caller();
+
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        match sessions.get(&principal) {
+            Some(session)
+                if session.client_principal == client_principal || session.principal == caller =>
+            {
+                sessions.remove(&principal);
+                Ok(session)
+            }
+            _ => Err(BackendError::not_found("session", principal)),
+        }
+    })
+}
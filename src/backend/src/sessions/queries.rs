@@ -0,0 +1,18 @@
+use ic_cdk::api::time;
+use ic_cdk::{caller, query};
+
+use super::guards::effective_caller;
+use super::models::{Session, SessionInvite};
+use super::stores::{client_sessions, invites_for};
+
+/// Sessions of the calling client, oldest first
+#[query]
+fn my_sessions() -> Vec<Session> {
+    client_sessions(&effective_caller())
+}
+
+/// Pending invites for the caller to act for a client, see `accept_session`
+#[query]
+fn my_session_invites() -> Vec<SessionInvite> {
+    invites_for(&caller(), time())
+}
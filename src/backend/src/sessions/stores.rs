@@ -0,0 +1,243 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::{Memory, SESSION_INVITES_MEMORY_ID, SESSIONS_MEMORY_ID, get_memory};
+use crate::users::stores::{CLIENTS, USERS, active_package};
+
+use super::models::{Session, SessionInvite};
+
+thread_local! {
+    /// Sessions of every client, keyed by session principal
+    pub static SESSIONS: RefCell<StableBTreeMap<Principal, Session, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SESSIONS_MEMORY_ID)));
+    /// Pending session invites, keyed by invited principal and inviting client
+    pub static SESSION_INVITES: RefCell<StableBTreeMap<(Principal, Principal), SessionInvite, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SESSION_INVITES_MEMORY_ID)));
+}
+
+/// Sessions acting for `client_principal`, oldest first
+pub fn client_sessions(client_principal: &Principal) -> Vec<Session> {
+    let mut sessions: Vec<Session> = SESSIONS.with(|sessions| {
+        sessions
+            .borrow()
+            .values()
+            .filter(|session| session.client_principal == *client_principal)
+            .collect()
+    });
+    sessions.sort_by_key(|session| session.created_at);
+    sessions
+}
+
+pub fn is_session(principal: &Principal) -> bool {
+    SESSIONS.with(|sessions| sessions.borrow().contains_key(principal))
+}
+
+/// The oldest `limit` sessions of `client_principal`, the ones allowed to act for it
+pub fn usable_sessions(client_principal: &Principal, limit: u64) -> Vec<Session> {
+    let mut sessions = client_sessions(client_principal);
+    sessions.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+    sessions
+}
+
+/// Whether `session` may act for its client. Only the oldest `max_allowed_sessions` of
+/// the client's active package count, so sessions left over a downgrade or lapse stay
+/// registered but unusable until others are revoked.
+pub fn is_usable(session: &Session) -> bool {
+    let limit =
+        active_package(&session.client_principal).map_or(0, |package| package.max_allowed_sessions);
+    usable_sessions(&session.client_principal, limit)
+        .iter()
+        .any(|usable| usable.principal == session.principal)
+}
+
+/// Whether `principal` could become a session: a principal with a profile, client
+/// record or session of its own must not be made to act for someone else
+pub fn check_session_candidate(principal: &Principal) -> BackendResult<()> {
+    if *principal == Principal::anonymous() {
+        return Err(BackendError::validation(
+            "principal",
+            "must not be anonymous",
+        ));
+    }
+    if USERS.with(|users| users.borrow().contains_key(principal))
+        || CLIENTS.with(|clients| clients.borrow().contains_key(principal))
+    {
+        return Err(BackendError::validation(
+            "principal",
+            "is already a registered user",
+        ));
+    }
+    if is_session(principal) {
+        return Err(BackendError::already_exists("session", principal));
+    }
+    Ok(())
+}
+
+/// Unexpired invites addressed to `principal`
+pub fn invites_for(principal: &Principal, now: u64) -> Vec<SessionInvite> {
+    SESSION_INVITES.with(|invites| {
+        invites
+            .borrow()
+            .range((*principal, Principal::management_canister())..)
+            .take_while(|((invited, _), _)| invited == principal)
+            .map(|(_, invite)| invite)
+            .filter(|invite| invite.expires_at > now)
+            .collect()
+    })
+}
+
+/// Turns the invite of `client_principal` to `principal` into a session, as long as
+/// the client has fewer than `limit` sessions. Drops every other invite to `principal`.
+pub fn accept_invite(
+    principal: Principal,
+    client_principal: Principal,
+    limit: u64,
+    now: u64,
+) -> BackendResult<Session> {
+    check_session_candidate(&principal)?;
+    let invite = SESSION_INVITES
+        .with(|invites| invites.borrow().get(&(principal, client_principal)))
+        .filter(|invite| invite.expires_at > now)
+        .ok_or_else(|| BackendError::not_found("session_invite", client_principal))?;
+    if client_sessions(&client_principal).len() as u64 >= limit {
+        return Err(BackendError::QuotaExceeded {
+            resource: "sessions".to_string(),
+            limit,
+        });
+    }
+
+    let session = Session {
+        principal,
+        client_principal,
+        label: invite.label,
+        created_at: now,
+        last_seen_at: now,
+    };
+    SESSIONS.with(|sessions| sessions.borrow_mut().insert(principal, session.clone()));
+    SESSION_INVITES.with(|invites| {
+        let mut invites = invites.borrow_mut();
+        let keys: Vec<(Principal, Principal)> = invites
+            .range((principal, Principal::management_canister())..)
+            .take_while(|((invited, _), _)| *invited == principal)
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            invites.remove(&key);
+        }
+    });
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_session(principal: Principal, client_principal: Principal, created_at: u64) {
+        let session = Session {
+            principal,
+            client_principal,
+            label: "device".to_string(),
+            created_at,
+            last_seen_at: created_at,
+        };
+        SESSIONS.with(|sessions| sessions.borrow_mut().insert(principal, session));
+    }
+
+    #[test]
+    fn only_the_oldest_sessions_are_usable() {
+        let client = Principal::from_slice(&[10]);
+        insert_session(Principal::from_slice(&[11]), client, 30);
+        insert_session(Principal::from_slice(&[12]), client, 10);
+        insert_session(Principal::from_slice(&[13]), client, 20);
+        insert_session(
+            Principal::from_slice(&[14]),
+            Principal::from_slice(&[15]),
+            0,
+        );
+
+        let usable: Vec<Principal> = usable_sessions(&client, 2)
+            .into_iter()
+            .map(|session| session.principal)
+            .collect();
+        assert_eq!(
+            usable,
+            vec![Principal::from_slice(&[12]), Principal::from_slice(&[13])]
+        );
+        assert!(usable_sessions(&client, 0).is_empty());
+        assert_eq!(usable_sessions(&client, 5).len(), 3);
+    }
+
+    fn invite(principal: Principal, client_principal: Principal, expires_at: u64) {
+        let invite = SessionInvite {
+            principal,
+            client_principal,
+            label: "device".to_string(),
+            created_at: 0,
+            expires_at,
+        };
+        SESSION_INVITES.with(|invites| {
+            invites
+                .borrow_mut()
+                .insert((principal, client_principal), invite)
+        });
+    }
+
+    #[test]
+    fn sessions_start_only_from_an_accepted_invite() {
+        let client = Principal::from_slice(&[20]);
+        let other_client = Principal::from_slice(&[21]);
+        let device = Principal::from_slice(&[22]);
+
+        // Nothing to accept without an invite of that client
+        assert!(accept_invite(device, client, 5, 10).is_err());
+
+        invite(device, client, 100);
+        invite(device, other_client, 100);
+        assert_eq!(invites_for(&device, 10).len(), 2);
+        assert!(accept_invite(device, client, 5, 100).is_err());
+
+        let session = accept_invite(device, client, 5, 10).unwrap();
+        assert_eq!(session.client_principal, client);
+        assert!(is_session(&device));
+        assert!(invites_for(&device, 10).is_empty());
+        assert!(accept_invite(device, other_client, 5, 10).is_err());
+    }
+
+    #[test]
+    fn registered_users_cannot_become_sessions() {
+        let client = Principal::from_slice(&[23]);
+        let user = Principal::from_slice(&[24]);
+        let profile = crate::users::models::Profile {
+            principal: user,
+            email: None,
+            first_name: None,
+            last_name: None,
+            image_hash: None,
+            date_added: 0,
+            last_updated: 0,
+        };
+        USERS.with(|users| users.borrow_mut().insert(user, profile));
+        invite(user, client, 100);
+
+        assert!(check_session_candidate(&user).is_err());
+        assert!(accept_invite(user, client, 5, 10).is_err());
+        assert!(!is_session(&user));
+    }
+
+    #[test]
+    fn accepting_respects_the_session_limit() {
+        let client = Principal::from_slice(&[25]);
+        insert_session(Principal::from_slice(&[26]), client, 0);
+        let device = Principal::from_slice(&[27]);
+        invite(device, client, 100);
+
+        assert!(matches!(
+            accept_invite(device, client, 1, 10),
+            Err(BackendError::QuotaExceeded { .. })
+        ));
+        assert!(accept_invite(device, client, 2, 10).is_ok());
+    }
+}
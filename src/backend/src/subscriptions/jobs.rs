@@ -0,0 +1,442 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use candid::Principal;
+use ic_cdk::api::time;
+
+use crate::config::stores::{free_package_uuid, grace_period_ns, subscription_duration_ns};
+use crate::transactions::guards::PrincipalLock;
+use crate::transactions::models::TokenAmount;
+use crate::transactions::payments::collect_payment;
+use crate::users::models::{ClientPackageSubscription, SubscriptionState};
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES};
+
+use super::models::{InvoiceStatus, SubscriptionEventKind};
+use super::plans::set_active_package;
+use super::stores::{Charge, issue_invoice, record_event};
+use super::terms::{assign_free_package_to_users, start_free_term};
+
+/// How often subscriptions are checked for renewal and expiry
+const RENEWAL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Auto-renewing subscriptions are charged this long before their term ends: 1 day
+const RENEWAL_LEAD_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    /// Whether a run of the renewal job is still awaiting the ledger
+    static RENEWALS_RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks a run of the renewal job as in flight until dropped, which ic-cdk also does
+/// when a callback traps
+struct RenewalRun;
+
+impl RenewalRun {
+    /// `None` while an earlier run hasn't finished
+    fn start() -> Option<Self> {
+        RENEWALS_RUNNING.with(|running| (!running.replace(true)).then_some(RenewalRun))
+    }
+}
+
+impl Drop for RenewalRun {
+    fn drop(&mut self) {
+        RENEWALS_RUNNING.with(|running| running.set(false));
+    }
+}
+
+/// Users put on the free package per message of the backfill
+const FREE_PACKAGE_BATCH: usize = 500;
+
+/// Puts registered users who aren't clients on the free package, in batches of one
+/// message each so the instruction limit isn't hit. Runs when a free package is set,
+/// and again after upgrades in case an upgrade cut the last run short.
+pub fn schedule_free_package_backfill() {
+    if free_package_uuid().is_some() {
+        backfill_free_package(None);
+    }
+}
+
+fn backfill_free_package(after: Option<Principal>) {
+    ic_cdk_timers::set_timer(Duration::ZERO, move || {
+        if let Some(last) = assign_free_package_to_users(after, FREE_PACKAGE_BATCH) {
+            backfill_free_package(Some(last));
+        }
+    });
+}
+
+/// Starts the job that renews and expires subscriptions. Timers don't survive
+/// upgrades, so this runs from both init and post_upgrade.
+pub fn schedule_subscription_renewals() {
+    ic_cdk_timers::set_timer_interval(RENEWAL_INTERVAL, || ic_cdk::spawn(process_subscriptions()));
+}
+
+async fn process_subscriptions() {
+    // Runs that outlast the interval would otherwise charge the same clients twice
+    let Some(_run) = RenewalRun::start() else {
+        return;
+    };
+    let due_before = time() + RENEWAL_LEAD_NS;
+    let principals: BTreeMap<String, Principal> = CLIENTS.with(|clients| {
+        clients
+            .borrow()
+            .iter()
+            .map(|(principal, client)| (client.uuid, principal))
+            .collect()
+    });
+    let due: Vec<(Principal, String)> = CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions
+            .borrow()
+            .iter()
+            .filter(|(_, subscription)| is_due(subscription, due_before))
+            .filter_map(|(client_uuid, _)| {
+                principals
+                    .get(&client_uuid)
+                    .map(|principal| (*principal, client_uuid))
+            })
+            .collect()
+    });
+
+    for (principal, client_uuid) in due {
+        process_subscription(principal, client_uuid).await;
+    }
+}
+
+/// Renews one due subscription, or moves it along Active → Grace → Expired/Suspended
+/// when it can't be renewed. Lapsed clients fall back to the free package, if any.
+async fn process_subscription(principal: Principal, client_uuid: String) {
+    // A payment of the client is in flight, the next run picks this one up again
+    let Ok(_lock) = PrincipalLock::acquire(principal) else {
+        return;
+    };
+    // Renewed or changed since the due list was made
+    let Some(subscription) =
+        load(&client_uuid).filter(|subscription| is_due(subscription, time() + RENEWAL_LEAD_NS))
+    else {
+        return;
+    };
+    let package_uuid = subscription.subscription_package_uuid.clone();
+    // A scheduled plan change takes effect with the renewal
+    let renewal_package_uuid = subscription
+        .pending_package_uuid
+        .clone()
+        .unwrap_or_else(|| package_uuid.clone());
+
+    let mut failure = None;
+    // Renewals are charged in the token the subscription was paid in
+    let mut price = TokenAmount::zero(subscription.token.clone());
+    if subscription.auto_renew.unwrap_or(false) {
+        let renewal_price = SUBSCRIPTION_PACKAGES
+            .with(|packages| packages.borrow().get(&renewal_package_uuid))
+            .ok_or_else(|| "subscription package no longer exists".to_string())
+            .and_then(|package| {
+                package
+                    .price_in(Some(subscription.token.ledger_canister_id))
+                    .map_err(|err| format!("{:?}", err))
+            });
+        match renewal_price {
+            Ok(renewal_price) => {
+                price = renewal_price;
+                match collect_payment(principal, &price).await {
+                    Ok(block_index) => {
+                        if let Some(renewed) =
+                            renew(&client_uuid, &renewal_package_uuid, &price, block_index)
+                        {
+                            record_event(
+                                principal,
+                                &renewal_package_uuid,
+                                SubscriptionEventKind::Renewed { block_index },
+                            );
+                            issue_invoice(
+                                Charge {
+                                    client_principal: principal,
+                                    client_uuid: &client_uuid,
+                                    subscription_package_uuid: &renewal_package_uuid,
+                                    period_start: renewed.period_start.unwrap_or_default(),
+                                    period_end: renewed.expires_at,
+                                    price,
+                                    block_index,
+                                    promo_code: None,
+                                },
+                                InvoiceStatus::Paid,
+                            );
+                        }
+                        return;
+                    }
+                    Err(err) => failure = Some(format!("{:?}", err)),
+                }
+            }
+            Err(reason) => failure = Some(reason),
+        }
+    }
+
+    // The subscription may have changed while awaiting the ledger
+    let Some(mut subscription) = load(&client_uuid) else {
+        return;
+    };
+    let Some(lapse) = lapse(&subscription, time(), grace_period_ns()) else {
+        return;
+    };
+
+    if let (SubscriptionState::Grace, Some(reason)) = (lapse.state, failure) {
+        issue_invoice(
+            Charge {
+                client_principal: principal,
+                client_uuid: &client_uuid,
+                subscription_package_uuid: &renewal_package_uuid,
+                period_start: subscription.expires_at,
+                period_end: subscription.expires_at + subscription_duration_ns(),
+                price,
+                block_index: None,
+                promo_code: None,
+            },
+            InvoiceStatus::Failed,
+        );
+        record_event(
+            principal,
+            &package_uuid,
+            SubscriptionEventKind::RenewalFailed { reason },
+        );
+    }
+    record_event(principal, &package_uuid, lapse.event);
+    subscription.state = Some(lapse.state);
+    CLIENT_SUBSCRIPTIONS
+        .with(|subscriptions| subscriptions.borrow_mut().insert(client_uuid, subscription));
+
+    if lapse.falls_back {
+        start_free_term(principal);
+    }
+}
+
+/// Whether `subscription` needs renewing, or moving towards expiry, by `due_before`
+fn is_due(subscription: &ClientPackageSubscription, due_before: u64) -> bool {
+    subscription.expires_at <= due_before
+        && matches!(
+            subscription.state(),
+            SubscriptionState::Active | SubscriptionState::Grace
+        )
+}
+
+/// How a subscription that wasn't renewed moves on
+#[derive(Debug)]
+struct Lapse {
+    state: SubscriptionState,
+    event: SubscriptionEventKind,
+    /// Whether the client drops to the free package
+    falls_back: bool,
+}
+
+/// Where a subscription that wasn't renewed stands at `now`: `Active` turns `Grace`
+/// once the term ended, and `Grace` turns `Suspended` with auto-renew on, or `Expired`
+/// without, once the grace period passed too. `None` while nothing changes.
+fn lapse(
+    subscription: &ClientPackageSubscription,
+    now: u64,
+    grace_period_ns: u64,
+) -> Option<Lapse> {
+    if subscription.expires_at > now {
+        return None;
+    }
+
+    match subscription.state() {
+        SubscriptionState::Active => Some(Lapse {
+            state: SubscriptionState::Grace,
+            event: SubscriptionEventKind::GraceStarted,
+            falls_back: false,
+        }),
+        SubscriptionState::Grace if now >= subscription.expires_at + grace_period_ns => {
+            Some(if subscription.auto_renew.unwrap_or(false) {
+                Lapse {
+                    state: SubscriptionState::Suspended,
+                    event: SubscriptionEventKind::Suspended,
+                    falls_back: true,
+                }
+            } else {
+                Lapse {
+                    state: SubscriptionState::Expired,
+                    event: SubscriptionEventKind::Expired,
+                    falls_back: true,
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+fn load(client_uuid: &String) -> Option<ClientPackageSubscription> {
+    CLIENT_SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().get(client_uuid))
+}
+
+/// Extends the subscription by one term of `package_uuid`
+fn renew(
+    client_uuid: &String,
+    package_uuid: &str,
+    price: &TokenAmount,
+    block_index: Option<u64>,
+) -> Option<ClientPackageSubscription> {
+    let subscription = renewed(
+        load(client_uuid)?,
+        package_uuid,
+        price,
+        block_index,
+        time(),
+        subscription_duration_ns(),
+    );
+    set_active_package(client_uuid, package_uuid);
+
+    CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions
+            .borrow_mut()
+            .insert(client_uuid.clone(), subscription.clone())
+    });
+    Some(subscription)
+}
+
+/// `subscription` after a paid renewal at `now`. Renewals during the grace period start
+/// the new term now, since the lapsed time wasn't covered.
+fn renewed(
+    mut subscription: ClientPackageSubscription,
+    package_uuid: &str,
+    price: &TokenAmount,
+    block_index: Option<u64>,
+    now: u64,
+    duration_ns: u64,
+) -> ClientPackageSubscription {
+    let period_start = subscription.expires_at.max(now);
+    subscription.period_start = Some(period_start);
+    subscription.expires_at = period_start + duration_ns;
+    subscription.subscription_package_uuid = package_uuid.to_string();
+    subscription.pending_package_uuid = None;
+    subscription.trial = None;
+    subscription.amount = price.amount.clone();
+    subscription.token = price.token.clone();
+    subscription.block_index = block_index;
+    subscription.state = Some(SubscriptionState::Active);
+    subscription
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::models::TokenDescriptor;
+    use candid::Nat;
+
+    const GRACE_NS: u64 = 50;
+
+    fn subscription(state: SubscriptionState, auto_renew: bool) -> ClientPackageSubscription {
+        ClientPackageSubscription {
+            client_uuid: "client".to_string(),
+            subscription_package_uuid: "basic".to_string(),
+            amount: Nat::from(100u64),
+            token: TokenDescriptor::default_token(),
+            expires_at: 1_000,
+            block_index: None,
+            period_start: Some(0),
+            auto_renew: Some(auto_renew),
+            state: Some(state),
+            pending_package_uuid: Some("pro".to_string()),
+            trial: Some(true),
+        }
+    }
+
+    #[test]
+    fn only_unrenewed_subscriptions_are_due() {
+        let active = subscription(SubscriptionState::Active, true);
+        assert!(is_due(&active, 1_000));
+        assert!(!is_due(&active, 999));
+        assert!(is_due(&subscription(SubscriptionState::Grace, true), 1_000));
+
+        // A renewal in between moves the term out of reach
+        let price = TokenAmount::zero(TokenDescriptor::default_token());
+        let renewed = renewed(active, "basic", &price, None, 900, 1_000);
+        assert!(!is_due(&renewed, 1_000));
+
+        for state in [SubscriptionState::Expired, SubscriptionState::Suspended] {
+            assert!(!is_due(&subscription(state, true), 10_000));
+        }
+    }
+
+    #[test]
+    fn renewal_runs_do_not_overlap() {
+        let run = RenewalRun::start().unwrap();
+        assert!(RenewalRun::start().is_none());
+        drop(run);
+        assert!(RenewalRun::start().is_some());
+    }
+
+    #[test]
+    fn active_subscriptions_enter_grace_when_the_term_ends() {
+        let active = subscription(SubscriptionState::Active, true);
+        assert!(lapse(&active, 999, GRACE_NS).is_none());
+
+        let lapse = lapse(&active, 1_000, GRACE_NS).unwrap();
+        assert_eq!(lapse.state, SubscriptionState::Grace);
+        assert!(matches!(lapse.event, SubscriptionEventKind::GraceStarted));
+        assert!(!lapse.falls_back);
+    }
+
+    #[test]
+    fn grace_ends_in_suspension_with_auto_renew() {
+        let grace = subscription(SubscriptionState::Grace, true);
+        assert!(lapse(&grace, 1_049, GRACE_NS).is_none());
+
+        let lapse = lapse(&grace, 1_050, GRACE_NS).unwrap();
+        assert_eq!(lapse.state, SubscriptionState::Suspended);
+        assert!(matches!(lapse.event, SubscriptionEventKind::Suspended));
+        assert!(lapse.falls_back);
+    }
+
+    #[test]
+    fn grace_ends_in_expiry_without_auto_renew() {
+        let grace = subscription(SubscriptionState::Grace, false);
+
+        let lapse = lapse(&grace, 1_050, GRACE_NS).unwrap();
+        assert_eq!(lapse.state, SubscriptionState::Expired);
+        assert!(matches!(lapse.event, SubscriptionEventKind::Expired));
+        assert!(lapse.falls_back);
+    }
+
+    #[test]
+    fn lapsed_subscriptions_stay_put() {
+        for state in [SubscriptionState::Expired, SubscriptionState::Suspended] {
+            assert!(lapse(&subscription(state, true), 10_000, GRACE_NS).is_none());
+        }
+    }
+
+    #[test]
+    fn renewals_extend_the_term() {
+        let price = TokenAmount {
+            token: TokenDescriptor::default_token(),
+            amount: Nat::from(250u64),
+        };
+
+        // Early renewals continue where the term ends
+        let early = renewed(
+            subscription(SubscriptionState::Active, true),
+            "pro",
+            &price,
+            Some(7),
+            900,
+            1_000,
+        );
+        assert_eq!((early.period_start, early.expires_at), (Some(1_000), 2_000));
+        assert_eq!(early.subscription_package_uuid, "pro");
+        assert_eq!(early.amount, 250u64);
+        assert_eq!(early.block_index, Some(7));
+        assert_eq!(early.pending_package_uuid, None);
+        assert_eq!(early.trial, None);
+        assert_eq!(early.state(), SubscriptionState::Active);
+
+        // Renewals during grace start now
+        let late = renewed(
+            subscription(SubscriptionState::Grace, true),
+            "basic",
+            &price,
+            None,
+            1_030,
+            1_000,
+        );
+        assert_eq!((late.period_start, late.expires_at), (Some(1_030), 2_030));
+        assert_eq!(late.state(), SubscriptionState::Active);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod jobs;
+pub mod models;
+pub mod mutations;
+pub mod plans;
+pub mod queries;
+pub mod stores;
+pub mod terms;
@@ -0,0 +1,145 @@
+use candid::{CandidType, Nat, Principal};
+use serde::Deserialize;
+
+use crate::common::memory::impl_candid_storable;
+use crate::migrations::v4_token_amounts::LegacyInvoice;
+use crate::transactions::models::TokenDescriptor;
+
+/// An entry of the subscription event log
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SubscriptionEvent {
+    pub client_principal: Principal,
+    pub subscription_package_uuid: String,
+    pub kind: SubscriptionEventKind,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum SubscriptionEventKind {
+    Subscribed {
+        block_index: Option<u64>,
+    },
+    Renewed {
+        block_index: Option<u64>,
+    },
+    TrialStarted {
+        ends_at: u64,
+    },
+    /// The term ended without a successful renewal
+    RenewalFailed {
+        reason: String,
+    },
+    /// Switched to the event's package mid-term
+    PlanChanged {
+        from_package_uuid: String,
+        block_index: Option<u64>,
+    },
+    /// The event's package replaces the current one at the next renewal
+    PlanChangeScheduled {
+        effective_at: u64,
+    },
+    GraceStarted,
+    Expired,
+    Suspended,
+}
+
+/// A charge for one subscription term. Invoices are never changed once issued.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Invoice {
+    pub number: u64,
+    pub client_principal: Principal,
+    pub client_uuid: String,
+    pub subscription_package_uuid: String,
+    pub period_start: u64,
+    pub period_end: u64,
+    /// Charged amount in base units of `token`
+    pub amount: Nat,
+    pub token: TokenDescriptor,
+    /// `None` for prepaid, free and failed charges
+    pub block_index: Option<u64>,
+    pub status: InvoiceStatus,
+    pub issued_at: u64,
+    /// Promotion code that discounted `amount`
+    pub promo_code: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum InvoiceStatus {
+    Paid,
+    /// A renewal charge the ledger or prepaid balance didn't cover
+    Failed,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Deserialize)]
+pub struct InvoiceFilter {
+    pub status: Option<InvoiceStatus>,
+}
+
+/// Revenue in one token, overall or from one package
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TokenRevenue {
+    pub token: TokenDescriptor,
+    pub invoice_count: u64,
+    pub amount: Nat,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PackageRevenue {
+    pub subscription_package_uuid: String,
+    pub by_token: Vec<TokenRevenue>,
+}
+
+/// Paid invoices issued within `[from, to)`. Amounts in different tokens are never
+/// added up.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RevenueReport {
+    pub from: u64,
+    pub to: u64,
+    pub invoice_count: u64,
+    pub by_token: Vec<TokenRevenue>,
+    pub by_package: Vec<PackageRevenue>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum PlanChangeKind {
+    Upgrade,
+    Downgrade,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum PlanChangeTiming {
+    /// Switch now, settling the remaining time of both packages
+    Immediately,
+    /// Switch at the next renewal, without proration
+    EndOfPeriod,
+}
+
+/// Cost of switching packages. All amounts are in base units of `token`, the token
+/// the subscription is paid in.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PlanChangeQuote {
+    pub from_package_uuid: String,
+    pub to_package_uuid: String,
+    pub kind: PlanChangeKind,
+    pub timing: PlanChangeTiming,
+    pub token: TokenDescriptor,
+    /// Unused share of what was paid for the current term
+    pub credit: Nat,
+    /// Share of the new package's price for the rest of the term
+    pub charge: Nat,
+    /// Charged when the change is made
+    pub amount_due: Nat,
+    /// Added to the prepaid balance when the change is made
+    pub refund: Nat,
+    pub effective_at: u64,
+}
+
+/// The one trial a principal may take
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Trial {
+    pub subscription_package_uuid: String,
+    pub started_at: u64,
+    pub ends_at: u64,
+}
+
+impl_candid_storable!(SubscriptionEvent, Invoice | LegacyInvoice, Trial);
@@ -0,0 +1,169 @@
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_cdk::{caller, update};
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::config::stores::trial_duration_ns;
+use crate::sessions::stores::is_session;
+use crate::transactions::guards::PrincipalLock;
+use crate::transactions::models::TokenAmount;
+use crate::transactions::payments::collect_payment;
+use crate::users::guards::require_client;
+use crate::users::models::ClientPackageSubscription;
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, SUBSCRIPTION_PACKAGES, active_subscription};
+
+use super::models::{
+    InvoiceStatus, PlanChangeQuote, PlanChangeTiming, SubscriptionEventKind, Trial,
+};
+use super::plans::{apply_plan_change, plan_price, quote_plan_change, schedule_plan_change};
+use super::stores::{Charge, TRIALS, is_paid_term, issue_invoice, record_event};
+use super::terms::{NewTerm, start_term};
+
+/// Turns automatic renewal of the calling client's subscription on or off. Sessions
+/// can't change it.
+#[update]
+fn set_auto_renew(enabled: bool) -> BackendResult<ClientPackageSubscription> {
+    // Renewals charge the client, so only the client principal decides on them
+    if is_session(&caller()) {
+        return Err(BackendError::Unauthorized);
+    }
+    let client = require_client()?;
+
+    CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let mut subscription = subscriptions
+            .get(&client.uuid)
+            .ok_or_else(|| BackendError::not_found("subscription", &client.uuid))?;
+        subscription.auto_renew = Some(enabled);
+        subscriptions.insert(client.uuid, subscription.clone());
+        Ok(subscription)
+    })
+}
+
+/// Starts the caller's one-time trial of a paid package. The trial renews into a
+/// paid term in the `token` ledger's token, or the package's default one when `None`,
+/// unless auto-renew is turned off.
+#[update]
+fn start_trial(
+    subscription_package_uuid: String,
+    token: Option<Principal>,
+) -> BackendResult<ClientPackageSubscription> {
+    let user_principal = caller();
+    // Sessions act for their client and can't become clients themselves
+    if is_session(&user_principal) {
+        return Err(BackendError::Unauthorized);
+    }
+    if TRIALS.with(|trials| trials.borrow().contains_key(&user_principal)) {
+        return Err(BackendError::already_exists("trial", user_principal));
+    }
+    let package = SUBSCRIPTION_PACKAGES
+        .with(|packages| packages.borrow().get(&subscription_package_uuid))
+        .ok_or_else(|| {
+            BackendError::not_found("subscription_package", &subscription_package_uuid)
+        })?;
+    let price = package.price_in(token)?;
+    if price.amount == 0u64 {
+        return Err(BackendError::validation(
+            "subscription_package_uuid",
+            "trials are only offered on paid packages",
+        ));
+    }
+    let on_paid_term = active_subscription(&user_principal)
+        .is_some_and(|subscription| is_paid_term(&user_principal, &subscription));
+    if on_paid_term {
+        return Err(BackendError::validation(
+            "subscription",
+            "already on a paid subscription",
+        ));
+    }
+
+    let subscription = start_term(NewTerm {
+        client_principal: user_principal,
+        subscription_package_uuid: &subscription_package_uuid,
+        price: TokenAmount::zero(price.token),
+        duration_ns: trial_duration_ns(),
+        block_index: None,
+        trial: true,
+        // Converts to a paid term unless the client opts out
+        auto_renew: Some(true),
+    });
+    let trial = Trial {
+        subscription_package_uuid: subscription_package_uuid.clone(),
+        started_at: subscription.period_start.unwrap_or_default(),
+        ends_at: subscription.expires_at,
+    };
+    TRIALS.with(|trials| trials.borrow_mut().insert(user_principal, trial));
+    record_event(
+        user_principal,
+        &subscription_package_uuid,
+        SubscriptionEventKind::TrialStarted {
+            ends_at: subscription.expires_at,
+        },
+    );
+
+    Ok(subscription)
+}
+
+/// Moves the calling client to another package, as priced by `preview_plan_change`.
+/// Sessions can't change plans.
+/// Immediate changes charge `amount_due` and credit `refund` to the prepaid balance;
+/// end-of-period changes are applied by the next renewal.
+#[update]
+async fn change_plan(
+    to_package_uuid: String,
+    timing: PlanChangeTiming,
+) -> BackendResult<PlanChangeQuote> {
+    // Billing is left to the client principal, sessions can't spend its money
+    if is_session(&caller()) {
+        return Err(BackendError::Unauthorized);
+    }
+    let client = require_client()?;
+    let _lock = PrincipalLock::acquire(client.principal)?;
+    let quote = quote_plan_change(&client, &to_package_uuid, timing)?;
+
+    if timing == PlanChangeTiming::EndOfPeriod {
+        schedule_plan_change(&client, &quote);
+        record_event(
+            client.principal,
+            &to_package_uuid,
+            SubscriptionEventKind::PlanChangeScheduled {
+                effective_at: quote.effective_at,
+            },
+        );
+        return Ok(quote);
+    }
+
+    // Priced before charging, so a failure can't leave a paid change unapplied
+    let price = plan_price(&quote)?;
+    let amount_due = TokenAmount {
+        token: quote.token.clone(),
+        amount: quote.amount_due.clone(),
+    };
+    let block_index = collect_payment(client.principal, &amount_due).await?;
+    let subscription = apply_plan_change(&client, &quote, price);
+
+    record_event(
+        client.principal,
+        &to_package_uuid,
+        SubscriptionEventKind::PlanChanged {
+            from_package_uuid: quote.from_package_uuid.clone(),
+            block_index,
+        },
+    );
+    issue_invoice(
+        Charge {
+            client_principal: client.principal,
+            client_uuid: &client.uuid,
+            subscription_package_uuid: &to_package_uuid,
+            period_start: time(),
+            period_end: subscription
+                .map_or(quote.effective_at, |subscription| subscription.expires_at),
+            price: amount_due,
+            block_index,
+            promo_code: None,
+        },
+        InvoiceStatus::Paid,
+    );
+
+    Ok(quote)
+}
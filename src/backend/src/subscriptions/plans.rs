@@ -0,0 +1,410 @@
+use candid::Nat;
+use ic_cdk::api::time;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::config::stores::subscription_duration_ns;
+use crate::transactions::models::TokenAmount;
+use crate::transactions::stores::credit_prepaid;
+use crate::users::models::{Client, ClientPackageSubscription, SubscriptionState};
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES};
+
+use super::models::{PlanChangeKind, PlanChangeQuote, PlanChangeTiming};
+
+/// Share of `amount` that covers `remaining_ns` of a term lasting `term_ns`, rounded
+/// down
+pub fn prorate(amount: &Nat, remaining_ns: u64, term_ns: u64) -> Nat {
+    if term_ns == 0 {
+        return Nat::from(0u8);
+    }
+    amount.clone() * Nat::from(remaining_ns.min(term_ns)) / Nat::from(term_ns)
+}
+
+/// Prices moving `client`'s active subscription to `to_package_uuid`, in the token the
+/// subscription is paid in
+pub fn quote_plan_change(
+    client: &Client,
+    to_package_uuid: &str,
+    timing: PlanChangeTiming,
+) -> BackendResult<PlanChangeQuote> {
+    quote_plan_change_at(
+        client,
+        to_package_uuid,
+        timing,
+        time(),
+        subscription_duration_ns(),
+    )
+}
+
+/// `quote_plan_change` at `now`, for subscriptions whose full term lasts `duration_ns`
+fn quote_plan_change_at(
+    client: &Client,
+    to_package_uuid: &str,
+    timing: PlanChangeTiming,
+    now: u64,
+    duration_ns: u64,
+) -> BackendResult<PlanChangeQuote> {
+    let subscription = CLIENT_SUBSCRIPTIONS
+        .with(|subscriptions| subscriptions.borrow().get(&client.uuid))
+        .filter(|subscription| {
+            subscription.state() == SubscriptionState::Active && subscription.expires_at > now
+        })
+        .ok_or_else(|| {
+            BackendError::validation("subscription", "no active subscription to change")
+        })?;
+    if subscription.subscription_package_uuid == to_package_uuid {
+        return Err(BackendError::validation(
+            "to_package_uuid",
+            "is already the current package",
+        ));
+    }
+    let to_package = SUBSCRIPTION_PACKAGES
+        .with(|packages| packages.borrow().get(&to_package_uuid.to_string()))
+        .ok_or_else(|| BackendError::not_found("subscription_package", to_package_uuid))?;
+
+    let paid = &subscription.amount;
+    let price = to_package
+        .price_in(Some(subscription.token.ledger_canister_id))?
+        .amount;
+    let kind = if price > *paid {
+        PlanChangeKind::Upgrade
+    } else {
+        PlanChangeKind::Downgrade
+    };
+
+    let (credit, charge, effective_at) = match timing {
+        PlanChangeTiming::Immediately => {
+            let period_start = subscription
+                .period_start
+                .unwrap_or_else(|| subscription.expires_at.saturating_sub(duration_ns));
+            let term_ns = subscription.expires_at.saturating_sub(period_start);
+            let remaining_ns = subscription.expires_at - now;
+            // What was paid covered the current term, which trials and other shortened
+            // terms make shorter than the full one the new price is for
+            (
+                prorate(paid, remaining_ns, term_ns),
+                prorate(&price, remaining_ns, duration_ns),
+                now,
+            )
+        }
+        PlanChangeTiming::EndOfPeriod => (Nat::from(0u8), Nat::from(0u8), subscription.expires_at),
+    };
+
+    let amount_due = saturating_sub(&charge, &credit);
+    let refund = saturating_sub(&credit, &charge);
+    Ok(PlanChangeQuote {
+        from_package_uuid: subscription.subscription_package_uuid,
+        to_package_uuid: to_package.uuid,
+        kind,
+        timing,
+        token: subscription.token,
+        credit,
+        charge,
+        amount_due,
+        refund,
+        effective_at,
+    })
+}
+
+/// Price of a term of the package `quote` moves to, in the token of `quote`
+pub fn plan_price(quote: &PlanChangeQuote) -> BackendResult<TokenAmount> {
+    SUBSCRIPTION_PACKAGES
+        .with(|packages| packages.borrow().get(&quote.to_package_uuid))
+        .ok_or_else(|| BackendError::not_found("subscription_package", &quote.to_package_uuid))?
+        .price_in(Some(quote.token.ledger_canister_id))
+}
+
+/// Makes `client` renew into the package of `quote` at the end of the term
+pub fn schedule_plan_change(client: &Client, quote: &PlanChangeQuote) {
+    CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        if let Some(mut subscription) = subscriptions.get(&client.uuid) {
+            subscription.pending_package_uuid = Some(quote.to_package_uuid.clone());
+            subscriptions.insert(client.uuid.clone(), subscription);
+        }
+    });
+}
+
+/// Moves `client` to the package of a paid immediate `quote` for the rest of the term,
+/// renewing at `price` and crediting the refund to their prepaid balance. A trial
+/// switched this way is over, since the new package was paid for.
+pub fn apply_plan_change(
+    client: &Client,
+    quote: &PlanChangeQuote,
+    price: TokenAmount,
+) -> Option<ClientPackageSubscription> {
+    let subscription = CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let mut subscription = subscriptions.get(&client.uuid)?;
+        subscription.subscription_package_uuid = quote.to_package_uuid.clone();
+        subscription.pending_package_uuid = None;
+        subscription.trial = None;
+        subscription.amount = price.amount;
+        subscription.token = price.token;
+        subscriptions.insert(client.uuid.clone(), subscription.clone());
+        Some(subscription)
+    })?;
+    set_active_package(&client.uuid, &quote.to_package_uuid);
+    if quote.refund > 0u64 {
+        credit_prepaid(
+            &client.principal,
+            TokenAmount {
+                token: quote.token.clone(),
+                amount: quote.refund.clone(),
+            },
+        );
+    }
+    Some(subscription)
+}
+
+/// `a - b`, or zero when `b` is larger
+fn saturating_sub(a: &Nat, b: &Nat) -> Nat {
+    if a > b {
+        a.clone() - b.clone()
+    } else {
+        Nat::from(0u8)
+    }
+}
+
+/// Points the client record with `client_uuid` at `package_uuid`
+pub fn set_active_package(client_uuid: &str, package_uuid: &str) {
+    CLIENTS.with(|clients| {
+        let mut clients = clients.borrow_mut();
+        let found = clients
+            .iter()
+            .find(|(_, client)| client.uuid == client_uuid);
+        if let Some((principal, mut client)) = found {
+            client.active_subscription_uuid = Some(package_uuid.to_string());
+            clients.insert(principal, client);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::models::TokenDescriptor;
+    use crate::transactions::stores::prepaid_balances;
+    use candid::Principal;
+
+    const TERM_NS: u64 = 1_000;
+
+    fn price(amount: u64) -> TokenAmount {
+        TokenAmount {
+            token: TokenDescriptor::default_token(),
+            amount: Nat::from(amount),
+        }
+    }
+
+    fn insert_package(uuid: &str, prices: Vec<TokenAmount>) {
+        let package = crate::users::models::SubscriptionPackage {
+            uuid: uuid.to_string(),
+            name: uuid.to_string(),
+            prices,
+            storage_capacity_mb: 1,
+            monthly_requests: 1,
+            max_allowed_sessions: 1,
+            last_updated: 0,
+        };
+        SUBSCRIPTION_PACKAGES.with(|packages| {
+            packages.borrow_mut().insert(uuid.to_string(), package);
+        });
+    }
+
+    /// A client paying 1_000 for the term `[0, TERM_NS)` of "standard", which has
+    /// "basic" at 400 and "premium" at 2_000 beside it. Trials are free and only cover
+    /// the second half of the term.
+    fn setup(id: u8, trial: bool) -> Client {
+        insert_package("basic", vec![price(400)]);
+        insert_package("standard", vec![price(1_000)]);
+        insert_package("premium", vec![price(2_000)]);
+        let client = Client {
+            principal: Principal::from_slice(&[id]),
+            uuid: format!("client-{id}"),
+            active_subscription_uuid: Some("standard".to_string()),
+        };
+        CLIENTS.with(|clients| {
+            clients
+                .borrow_mut()
+                .insert(client.principal, client.clone())
+        });
+        let subscription = ClientPackageSubscription {
+            client_uuid: client.uuid.clone(),
+            subscription_package_uuid: "standard".to_string(),
+            amount: Nat::from(if trial { 0u64 } else { 1_000 }),
+            token: TokenDescriptor::default_token(),
+            expires_at: TERM_NS,
+            block_index: None,
+            period_start: Some(if trial { TERM_NS / 2 } else { 0 }),
+            auto_renew: Some(true),
+            state: Some(SubscriptionState::Active),
+            pending_package_uuid: None,
+            trial: trial.then_some(true),
+        };
+        CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+            subscriptions
+                .borrow_mut()
+                .insert(client.uuid.clone(), subscription)
+        });
+        client
+    }
+
+    fn subscription_of(client: &Client) -> ClientPackageSubscription {
+        CLIENT_SUBSCRIPTIONS
+            .with(|subscriptions| subscriptions.borrow().get(&client.uuid))
+            .unwrap()
+    }
+
+    #[test]
+    fn immediate_upgrades_charge_the_difference_for_the_rest_of_the_term() {
+        let client = setup(30, false);
+        let quote = quote_plan_change_at(
+            &client,
+            "premium",
+            PlanChangeTiming::Immediately,
+            750,
+            TERM_NS,
+        )
+        .unwrap();
+
+        assert_eq!(quote.kind, PlanChangeKind::Upgrade);
+        assert_eq!(quote.credit, 250u64);
+        assert_eq!(quote.charge, 500u64);
+        assert_eq!(quote.amount_due, 250u64);
+        assert_eq!(quote.refund, 0u64);
+        assert_eq!(quote.effective_at, 750);
+
+        let price = plan_price(&quote).unwrap();
+        let subscription = apply_plan_change(&client, &quote, price).unwrap();
+        assert_eq!(subscription.subscription_package_uuid, "premium");
+        assert_eq!(subscription.amount, 2_000u64);
+        assert_eq!(subscription.expires_at, TERM_NS);
+        assert_eq!(
+            CLIENTS
+                .with(|clients| clients.borrow().get(&client.principal))
+                .unwrap()
+                .active_subscription_uuid,
+            Some("premium".to_string())
+        );
+        assert!(prepaid_balances(&client.principal).is_empty());
+    }
+
+    #[test]
+    fn immediate_downgrades_refund_the_difference() {
+        let client = setup(31, false);
+        let quote = quote_plan_change_at(
+            &client,
+            "basic",
+            PlanChangeTiming::Immediately,
+            500,
+            TERM_NS,
+        )
+        .unwrap();
+
+        assert_eq!(quote.kind, PlanChangeKind::Downgrade);
+        assert_eq!(quote.credit, 500u64);
+        assert_eq!(quote.charge, 200u64);
+        assert_eq!(quote.amount_due, 0u64);
+        assert_eq!(quote.refund, 300u64);
+
+        let price = plan_price(&quote).unwrap();
+        apply_plan_change(&client, &quote, price).unwrap();
+        assert_eq!(subscription_of(&client).amount, 400u64);
+        let balances = prepaid_balances(&client.principal);
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].amount, 300u64);
+    }
+
+    #[test]
+    fn converting_a_trial_charges_the_full_price_rate() {
+        let client = setup(32, true);
+        let quote = quote_plan_change_at(
+            &client,
+            "premium",
+            PlanChangeTiming::Immediately,
+            750,
+            TERM_NS,
+        )
+        .unwrap();
+        // A quarter of the full term is left, not half of the trial's
+        assert_eq!(quote.credit, 0u64);
+        assert_eq!(quote.charge, 500u64);
+        assert_eq!(quote.amount_due, 500u64);
+
+        let price = plan_price(&quote).unwrap();
+        let subscription = apply_plan_change(&client, &quote, price).unwrap();
+        assert_eq!(subscription.trial, None);
+        assert_eq!(subscription.amount, 2_000u64);
+    }
+
+    #[test]
+    fn end_of_period_changes_wait_for_the_renewal() {
+        let client = setup(33, false);
+        let quote = quote_plan_change_at(
+            &client,
+            "basic",
+            PlanChangeTiming::EndOfPeriod,
+            500,
+            TERM_NS,
+        )
+        .unwrap();
+        assert_eq!(quote.amount_due, 0u64);
+        assert_eq!(quote.refund, 0u64);
+        assert_eq!(quote.effective_at, TERM_NS);
+
+        schedule_plan_change(&client, &quote);
+        let subscription = subscription_of(&client);
+        assert_eq!(subscription.subscription_package_uuid, "standard");
+        assert_eq!(subscription.pending_package_uuid, Some("basic".to_string()));
+    }
+
+    #[test]
+    fn plan_changes_are_validated() {
+        let client = setup(34, false);
+        let quote = |to: &str, now| {
+            quote_plan_change_at(&client, to, PlanChangeTiming::Immediately, now, TERM_NS)
+        };
+        assert!(quote("standard", 500).is_err());
+        assert!(quote("missing", 500).is_err());
+        // Nothing to change once the term is over
+        assert!(quote("premium", TERM_NS).is_err());
+
+        let other_token = TokenAmount {
+            token: TokenDescriptor {
+                ledger_canister_id: Principal::from_slice(&[99]),
+                symbol: "TKN".to_string(),
+                decimals: 6,
+            },
+            amount: Nat::from(5u64),
+        };
+        insert_package("elsewhere", vec![other_token]);
+        assert!(quote("elsewhere", 500).is_err());
+
+        let stranger = Client {
+            principal: Principal::from_slice(&[35]),
+            uuid: "client-35".to_string(),
+            active_subscription_uuid: None,
+        };
+        assert!(
+            quote_plan_change_at(
+                &stranger,
+                "premium",
+                PlanChangeTiming::Immediately,
+                500,
+                TERM_NS
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn prorate_covers_the_remaining_share() {
+        let prorate =
+            |amount: u64, remaining_ns, term_ns| prorate(&Nat::from(amount), remaining_ns, term_ns);
+        assert_eq!(prorate(3_000, 10, 30), 1_000u64);
+        assert_eq!(prorate(1_000, 0, 30), 0u64);
+        assert_eq!(prorate(1_000, 45, 30), 1_000u64);
+        assert_eq!(prorate(1_000, 1, 3), 333u64);
+        assert_eq!(prorate(1_000, 10, 0), 0u64);
+        assert_eq!(prorate(u64::MAX, 1, 2), u64::MAX / 2);
+    }
+}
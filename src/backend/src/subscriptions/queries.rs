@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use candid::{Nat, Principal};
+use ic_cdk::query;
+
+use crate::assets::models::Paginated;
+use crate::common::errors::BackendResult;
+use crate::roles::{guards::require_role, models::Role};
+use crate::sessions::guards::effective_caller;
+use crate::users::guards::require_client;
+
+use super::models::{
+    Invoice, InvoiceFilter, InvoiceStatus, PackageRevenue, PlanChangeQuote, PlanChangeTiming,
+    RevenueReport, SubscriptionEvent, TokenRevenue, Trial,
+};
+use super::plans::quote_plan_change;
+use super::stores::{INVOICES, TRIALS, client_events, client_invoice_numbers};
+
+/// Subscription events of the calling client, newest first
+#[query]
+fn my_subscription_events() -> Vec<SubscriptionEvent> {
+    client_events(&effective_caller())
+}
+
+/// Subscription events of any client (admins only)
+#[query]
+fn client_subscription_events(principal: Principal) -> BackendResult<Vec<SubscriptionEvent>> {
+    require_role(Role::Admin)?;
+
+    Ok(client_events(&principal))
+}
+
+/// The calling client's invoices, newest first
+#[query]
+fn my_invoices(opts: Option<Paginated<InvoiceFilter>>) -> Vec<Invoice> {
+    let client_principal = effective_caller();
+    let opts = opts.unwrap_or_default();
+    let status = opts.opts.and_then(|filter| filter.status);
+
+    INVOICES.with(|invoices| {
+        let invoices = invoices.borrow();
+        client_invoice_numbers(&client_principal)
+            .into_iter()
+            .filter_map(|number| invoices.get(&number))
+            .filter(|invoice| status.is_none_or(|status| invoice.status == status))
+            .skip(opts.offset.unwrap_or(0))
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .collect()
+    })
+}
+
+/// Revenue from paid invoices issued within `[from, to)`, per token and per package
+/// (admins only)
+#[query]
+fn revenue_report(from: u64, to: u64) -> BackendResult<RevenueReport> {
+    require_role(Role::Admin)?;
+
+    let mut by_token: BTreeMap<Principal, TokenRevenue> = BTreeMap::new();
+    let mut by_package: BTreeMap<String, BTreeMap<Principal, TokenRevenue>> = BTreeMap::new();
+    INVOICES.with(|invoices| {
+        for (_, invoice) in invoices.borrow().iter() {
+            if invoice.status != InvoiceStatus::Paid
+                || invoice.issued_at < from
+                || invoice.issued_at >= to
+            {
+                continue;
+            }
+            let package_revenue = by_package
+                .entry(invoice.subscription_package_uuid.clone())
+                .or_default();
+            for revenue in [&mut by_token, package_revenue] {
+                let revenue = revenue
+                    .entry(invoice.token.ledger_canister_id)
+                    .or_insert_with(|| TokenRevenue {
+                        token: invoice.token.clone(),
+                        invoice_count: 0,
+                        amount: Nat::from(0u8),
+                    });
+                revenue.invoice_count += 1;
+                revenue.amount += invoice.amount.clone();
+            }
+        }
+    });
+
+    let by_token: Vec<TokenRevenue> = by_token.into_values().collect();
+    Ok(RevenueReport {
+        from,
+        to,
+        invoice_count: by_token.iter().map(|revenue| revenue.invoice_count).sum(),
+        by_token,
+        by_package: by_package
+            .into_iter()
+            .map(|(subscription_package_uuid, by_token)| PackageRevenue {
+                subscription_package_uuid,
+                by_token: by_token.into_values().collect(),
+            })
+            .collect(),
+    })
+}
+
+/// The caller's trial, if they took one
+#[query]
+fn my_trial() -> Option<Trial> {
+    TRIALS.with(|trials| trials.borrow().get(&effective_caller()))
+}
+
+/// Prices moving the calling client to another package, without changing anything
+#[query]
+fn preview_plan_change(
+    to_package_uuid: String,
+    timing: PlanChangeTiming,
+) -> BackendResult<PlanChangeQuote> {
+    let client = require_client()?;
+
+    quote_plan_change(&client, &to_package_uuid, timing)
+}
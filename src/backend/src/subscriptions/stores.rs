@@ -0,0 +1,224 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::memory::{
+    CLIENT_EVENTS_MEMORY_ID, CLIENT_INVOICES_MEMORY_ID, INVOICES_MEMORY_ID, Memory,
+    SUBSCRIPTION_EVENTS_MEMORY_ID, TRIALS_MEMORY_ID, get_memory,
+};
+use crate::transactions::models::TokenAmount;
+use crate::users::models::ClientPackageSubscription;
+
+use super::models::{Invoice, InvoiceStatus, SubscriptionEvent, SubscriptionEventKind, Trial};
+
+thread_local! {
+    /// Append-only subscription event log, keyed by sequence number
+    pub static SUBSCRIPTION_EVENTS: RefCell<StableBTreeMap<u64, SubscriptionEvent, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SUBSCRIPTION_EVENTS_MEMORY_ID)));
+    /// Append-only invoice store, keyed by invoice number
+    pub static INVOICES: RefCell<StableBTreeMap<u64, Invoice, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(INVOICES_MEMORY_ID)));
+    /// Index of `INVOICES` by client principal and invoice number
+    pub static CLIENT_INVOICES: RefCell<StableBTreeMap<(Principal, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(CLIENT_INVOICES_MEMORY_ID)));
+    /// Index of `SUBSCRIPTION_EVENTS` by client principal and sequence number
+    pub static CLIENT_EVENTS: RefCell<StableBTreeMap<(Principal, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(CLIENT_EVENTS_MEMORY_ID)));
+    /// Trials taken, keyed by principal. Entries are never removed, so nobody gets a
+    /// second trial.
+    pub static TRIALS: RefCell<StableBTreeMap<Principal, Trial, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(TRIALS_MEMORY_ID)));
+}
+
+pub fn record_event(
+    client_principal: Principal,
+    subscription_package_uuid: &str,
+    kind: SubscriptionEventKind,
+) {
+    let event = SubscriptionEvent {
+        client_principal,
+        subscription_package_uuid: subscription_package_uuid.to_string(),
+        kind,
+        timestamp: time(),
+    };
+
+    SUBSCRIPTION_EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        let sequence = events
+            .last_key_value()
+            .map_or(0, |(sequence, _)| sequence + 1);
+        events.insert(sequence, event);
+        CLIENT_EVENTS.with(|index| index.borrow_mut().insert((client_principal, sequence), ()));
+    });
+}
+
+/// Events of `client_principal`, newest first
+pub fn client_events(client_principal: &Principal) -> Vec<SubscriptionEvent> {
+    let sequences: Vec<u64> = CLIENT_EVENTS.with(|index| {
+        index
+            .borrow()
+            .range((*client_principal, 0)..=(*client_principal, u64::MAX))
+            .map(|((_, sequence), _)| sequence)
+            .collect()
+    });
+    SUBSCRIPTION_EVENTS.with(|events| {
+        let events = events.borrow();
+        sequences
+            .into_iter()
+            .rev()
+            .filter_map(|sequence| events.get(&sequence))
+            .collect()
+    })
+}
+
+/// Invoice numbers of `client_principal`, newest first
+pub fn client_invoice_numbers(client_principal: &Principal) -> Vec<u64> {
+    CLIENT_INVOICES.with(|index| {
+        index
+            .borrow()
+            .range((*client_principal, 0)..=(*client_principal, u64::MAX))
+            .map(|((_, number), _)| number)
+            .rev()
+            .collect()
+    })
+}
+
+/// Details of a charge to be invoiced
+pub struct Charge<'a> {
+    pub client_principal: Principal,
+    pub client_uuid: &'a str,
+    pub subscription_package_uuid: &'a str,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub price: TokenAmount,
+    pub block_index: Option<u64>,
+    pub promo_code: Option<String>,
+}
+
+/// Appends an invoice for `charge` under the next invoice number, starting at 1.
+/// Charges of zero, such as free and prepaid-credit terms, aren't invoiced, unless a
+/// promotion took the whole price: that term was still bought.
+pub fn issue_invoice(charge: Charge, status: InvoiceStatus) -> Option<Invoice> {
+    if charge.price.amount == 0u64 && charge.promo_code.is_none() {
+        return None;
+    }
+
+    INVOICES.with(|invoices| {
+        let mut invoices = invoices.borrow_mut();
+        let number = invoices
+            .last_key_value()
+            .map_or(1, |(number, _)| number + 1);
+        let invoice = Invoice {
+            number,
+            client_principal: charge.client_principal,
+            client_uuid: charge.client_uuid.to_string(),
+            subscription_package_uuid: charge.subscription_package_uuid.to_string(),
+            period_start: charge.period_start,
+            period_end: charge.period_end,
+            amount: charge.price.amount,
+            token: charge.price.token,
+            block_index: charge.block_index,
+            status,
+            issued_at: time(),
+            promo_code: charge.promo_code,
+        };
+        invoices.insert(number, invoice.clone());
+        CLIENT_INVOICES.with(|index| {
+            index
+                .borrow_mut()
+                .insert((invoice.client_principal, number), ())
+        });
+        Some(invoice)
+    })
+}
+
+/// Whether the current term of `subscription`, held by `client_principal`, was paid for, as opposed to a free or
+/// trial term. Terms a promotion made free still have their invoice.
+pub fn is_paid_term(
+    client_principal: &Principal,
+    subscription: &ClientPackageSubscription,
+) -> bool {
+    subscription.amount > 0u64
+        || INVOICES.with(|invoices| {
+            let invoices = invoices.borrow();
+            client_invoice_numbers(client_principal)
+                .into_iter()
+                .filter_map(|number| invoices.get(&number))
+                .any(|invoice| {
+                    invoice.status == InvoiceStatus::Paid
+                        && invoice.period_end == subscription.expires_at
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::models::TokenDescriptor;
+    use crate::users::models::SubscriptionState;
+    use candid::Nat;
+
+    fn subscription(client_uuid: &str, amount: u64) -> ClientPackageSubscription {
+        ClientPackageSubscription {
+            client_uuid: client_uuid.to_string(),
+            subscription_package_uuid: "pro".to_string(),
+            amount: Nat::from(amount),
+            token: TokenDescriptor::default_token(),
+            expires_at: 2_000,
+            block_index: None,
+            period_start: Some(1_000),
+            auto_renew: None,
+            state: Some(SubscriptionState::Active),
+            pending_package_uuid: None,
+            trial: None,
+        }
+    }
+
+    fn insert_invoice(number: u64, client: u8, period_end: u64, status: InvoiceStatus) {
+        let client_principal = Principal::from_slice(&[client]);
+        let invoice = Invoice {
+            number,
+            client_principal,
+            client_uuid: format!("client-{client}"),
+            subscription_package_uuid: "pro".to_string(),
+            period_start: period_end - 1_000,
+            period_end,
+            amount: Nat::from(0u64),
+            token: TokenDescriptor::default_token(),
+            block_index: None,
+            status,
+            issued_at: 0,
+            promo_code: Some("FREE".to_string()),
+        };
+        INVOICES.with(|invoices| invoices.borrow_mut().insert(number, invoice));
+        CLIENT_INVOICES.with(|index| index.borrow_mut().insert((client_principal, number), ()));
+    }
+
+    #[test]
+    fn terms_are_paid_by_amount_or_invoice() {
+        let is_paid = |client: u8, amount| {
+            is_paid_term(
+                &Principal::from_slice(&[client]),
+                &subscription(&format!("client-{client}"), amount),
+            )
+        };
+        assert!(is_paid(1, 100));
+        assert!(!is_paid(2, 0));
+
+        // Fully discounted terms are recognized by their invoice
+        insert_invoice(1, 3, 2_000, InvoiceStatus::Paid);
+        assert!(is_paid(3, 0));
+
+        // Invoices of other terms or clients, or failed ones, don't count
+        insert_invoice(2, 4, 1_000, InvoiceStatus::Paid);
+        assert!(!is_paid(4, 0));
+        insert_invoice(3, 5, 2_000, InvoiceStatus::Failed);
+        assert!(!is_paid(5, 0));
+        assert_eq!(
+            client_invoice_numbers(&Principal::from_slice(&[3])),
+            vec![1]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::ops::Bound;
+
+use candid::Principal;
+use ic_cdk::api::time;
+
+use crate::common::utils::uuid::{IdKind, generate_unique_id};
+use crate::config::stores::{free_package_uuid, subscription_duration_ns};
+use crate::sessions::stores::is_session;
+use crate::transactions::models::{TokenAmount, TokenDescriptor};
+use crate::usage::stores::start_request_period;
+use crate::users::models::{Client, ClientPackageSubscription, SubscriptionState};
+use crate::users::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES, USERS};
+
+use super::models::SubscriptionEventKind;
+use super::stores::record_event;
+
+/// A subscription term that is about to start
+pub struct NewTerm<'a> {
+    pub client_principal: Principal,
+    pub subscription_package_uuid: &'a str,
+    /// Package price paid for the term, in the token later renewals are charged in
+    pub price: TokenAmount,
+    pub duration_ns: u64,
+    pub block_index: Option<u64>,
+    pub trial: bool,
+    /// Auto-renew setting of the term, `None` keeps the client's previous choice
+    pub auto_renew: Option<bool>,
+}
+
+/// Replaces the client's subscription with `term`, creating the client on their first
+/// subscription
+pub fn start_term(term: NewTerm) -> ClientPackageSubscription {
+    let now = time();
+    let expires_at = now + term.duration_ns;
+
+    let subscription = CLIENTS.with(|clients| {
+        let mut clients = clients.borrow_mut();
+        let mut client = clients
+            .get(&term.client_principal)
+            .unwrap_or_else(|| Client {
+                principal: term.client_principal,
+                uuid: generate_unique_id(IdKind::Client),
+                active_subscription_uuid: None,
+            });
+
+        let subscription = CLIENT_SUBSCRIPTIONS.with(|subscriptions| {
+            let mut subscriptions = subscriptions.borrow_mut();
+            let auto_renew = term.auto_renew.or_else(|| {
+                subscriptions
+                    .get(&client.uuid)
+                    .and_then(|previous| previous.auto_renew)
+            });
+            let subscription = ClientPackageSubscription {
+                client_uuid: client.uuid.clone(),
+                subscription_package_uuid: term.subscription_package_uuid.to_string(),
+                amount: term.price.amount,
+                token: term.price.token,
+                expires_at,
+                block_index: term.block_index,
+                period_start: Some(now),
+                auto_renew,
+                state: Some(SubscriptionState::Active),
+                pending_package_uuid: None,
+                trial: term.trial.then_some(true),
+            };
+            subscriptions.insert(client.uuid.clone(), subscription.clone());
+            subscription
+        });
+
+        client.active_subscription_uuid = Some(term.subscription_package_uuid.to_string());
+        clients.insert(term.client_principal, client);
+
+        subscription
+    });
+
+    // A new subscription term starts a new request period
+    start_request_period(&term.client_principal, expires_at);
+
+    subscription
+}
+
+/// Starts a term of the configured free package, if there is one. Returns `None`
+/// without a free package.
+pub fn start_free_term(client_principal: Principal) -> Option<ClientPackageSubscription> {
+    let package_uuid = free_package_uuid()?;
+    SUBSCRIPTION_PACKAGES.with(|packages| packages.borrow().get(&package_uuid))?;
+
+    let subscription = start_term(NewTerm {
+        client_principal,
+        subscription_package_uuid: &package_uuid,
+        price: TokenAmount::zero(TokenDescriptor::default_token()),
+        duration_ns: subscription_duration_ns(),
+        block_index: None,
+        trial: false,
+        auto_renew: Some(true),
+    });
+    record_event(
+        client_principal,
+        &package_uuid,
+        SubscriptionEventKind::Subscribed { block_index: None },
+    );
+    Some(subscription)
+}
+
+/// Makes a newly registered user a client on the free package
+pub fn assign_free_package(principal: Principal) {
+    if is_session(&principal) || CLIENTS.with(|clients| clients.borrow().contains_key(&principal)) {
+        return;
+    }
+    start_free_term(principal);
+}
+
+/// Puts up to `limit` registered users after `after` on the free package, if they
+/// aren't clients yet. Returns the last user handled, or `None` once every user is.
+pub fn assign_free_package_to_users(after: Option<Principal>, limit: usize) -> Option<Principal> {
+    let principals = users_after(after, limit);
+    for principal in &principals {
+        assign_free_package(*principal);
+    }
+    principals
+        .last()
+        .copied()
+        .filter(|_| principals.len() == limit)
+}
+
+/// Principals of up to `limit` users following `after` in key order
+fn users_after(after: Option<Principal>, limit: usize) -> Vec<Principal> {
+    let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+    USERS.with(|users| {
+        users
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(principal, _)| principal)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::models::Profile;
+
+    #[test]
+    fn users_are_walked_in_batches() {
+        for id in 1..=5u8 {
+            let principal = Principal::from_slice(&[200, id]);
+            let profile = Profile {
+                principal,
+                email: None,
+                first_name: None,
+                last_name: None,
+                image_hash: None,
+                date_added: 0,
+                last_updated: 0,
+            };
+            USERS.with(|users| users.borrow_mut().insert(principal, profile));
+        }
+        let first = users_after(None, 2);
+        assert_eq!(first.len(), 2);
+        let second = users_after(first.last().copied(), 2);
+        assert_eq!(second[0], Principal::from_slice(&[200, 3]));
+        assert_eq!(users_after(second.last().copied(), 10).len(), 1);
+    }
+}
@@ -1,54 +1,34 @@
-use candid::{CandidType, Principal};
-use ic_cdk::{call, caller, id};
-use serde::Deserialize;
+use candid::Principal;
+use ic_cdk::caller;
 
-#[derive(CandidType, Deserialize)]
-pub struct AccountBalanceArgs {
-    pub account: String,
-}
+use crate::common::errors::BackendResult;
+
+use super::icrc::{IcrcAccount, balance_of, treasury_account};
+use super::models::{TokenAmount, TokenDescriptor};
 
-#[derive(CandidType, Deserialize)]
-pub struct Tokens {
-    pub e8s: u64,
+/// Balance of `account` on the default ledger, through ICRC-1 so any configured
+/// ledger works
+async fn default_ledger_balance(account: IcrcAccount) -> BackendResult<TokenAmount> {
+    let token = TokenDescriptor::default_token();
+    let amount = balance_of(token.ledger_canister_id, account).await?;
+    Ok(TokenAmount { token, amount })
 }
 
 #[ic_cdk::query]
-pub async fn check_canister_balance() -> Result<u64, String> {
-    let canister_account = id().to_string();
-    let args = AccountBalanceArgs {
-        account: canister_account,
-    };
-
-    let result: Result<(Tokens,), _> = call(
-        Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap(),
-        "account_balance",
-        (args,),
-    )
-    .await;
-
-    match result {
-        Ok((tokens,)) => Ok(tokens.e8s),
-        Err(err) => Err(format!("Balance check failed: {:?}", err)),
-    }
+pub async fn check_canister_balance() -> BackendResult<TokenAmount> {
+    default_ledger_balance(treasury_account()).await
 }
 
 #[ic_cdk::query]
-pub async fn check_balance(account_ownder: Principal) -> Result<u64, String> {
-    let args = AccountBalanceArgs {
-        account: account_ownder.to_string(),
-    };
-
-    let ledger_canister = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
-
-    let result: Result<(Tokens,), _> = call(ledger_canister, "account_balance", (args,)).await;
-
-    match result {
-        Ok((tokens,)) => Ok(tokens.e8s),
-        Err(err) => Err(format!("Balance check failed: {:?}", err)),
-    }
+pub async fn check_balance(account_ownder: Principal) -> BackendResult<TokenAmount> {
+    default_ledger_balance(IcrcAccount {
+        owner: account_ownder,
+        subaccount: None,
+    })
+    .await
 }
 
 #[ic_cdk::query]
-pub async fn my_balance() -> Result<u64, String> {
+pub async fn my_balance() -> BackendResult<TokenAmount> {
     check_balance(caller()).await
 }
@@ -0,0 +1,87 @@
+use candid::Principal;
+use ic_cdk::{id, query, update};
+use sha2::{Digest, Sha256};
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::sessions::guards::effective_caller;
+use crate::users::guards::require_client;
+use crate::users::stores::accepted_token;
+
+use super::guards::PrincipalLock;
+use super::icrc::{IcrcAccount, balance_of, fee_of, transfer, treasury_account};
+use super::models::TokenAmount;
+use super::stores::{credit_prepaid, prepaid_balances};
+
+/// Subaccount of the canister that receives `client_uuid`'s deposits. Derived from the
+/// uuid alone, so it never changes and needs no storage.
+pub fn deposit_subaccount(client_uuid: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"deposit:");
+    hasher.update(client_uuid.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Account the calling client can send tokens to for topping up their prepaid balance,
+/// the same on every accepted ledger. Deposits are only credited once `claim_deposit`
+/// is called.
+#[query]
+fn get_deposit_account() -> BackendResult<IcrcAccount> {
+    let client = require_client()?;
+
+    Ok(IcrcAccount {
+        owner: id(),
+        subaccount: Some(deposit_subaccount(&client.uuid).to_vec()),
+    })
+}
+
+/// Sweeps whatever arrived in the caller's deposit account on `ledger`, or the default
+/// ledger when `None`, into the treasury and credits it, minus the ledger fee, to their
+/// prepaid balance in that token. Returns the new balance.
+#[update]
+async fn claim_deposit(ledger: Option<Principal>) -> BackendResult<TokenAmount> {
+    let client = require_client()?;
+    let token = accepted_token(ledger)
+        .ok_or_else(|| BackendError::validation("ledger", "no package is priced in this token"))?;
+    let ledger = token.ledger_canister_id;
+    let _lock = PrincipalLock::acquire(client.principal)?;
+    let subaccount = deposit_subaccount(&client.uuid);
+
+    let deposited = balance_of(
+        ledger,
+        IcrcAccount {
+            owner: id(),
+            subaccount: Some(subaccount.to_vec()),
+        },
+    )
+    .await?;
+    let fee = fee_of(ledger).await?;
+    if deposited <= fee {
+        return Err(BackendError::validation(
+            "deposit",
+            format!("no deposit above the ledger fee of {}", fee),
+        ));
+    }
+
+    let credited = deposited - fee;
+    transfer(
+        ledger,
+        Some(subaccount),
+        treasury_account(),
+        credited.clone(),
+    )
+    .await?;
+
+    Ok(credit_prepaid(
+        &client.principal,
+        TokenAmount {
+            token,
+            amount: credited,
+        },
+    ))
+}
+
+/// The calling client's prepaid balances, one per token they deposited
+#[query]
+fn my_prepaid_balances() -> Vec<TokenAmount> {
+    prepaid_balances(&effective_caller())
+}
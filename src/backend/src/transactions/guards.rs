@@ -0,0 +1,36 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+use candid::Principal;
+
+use crate::common::errors::{BackendError, BackendResult};
+
+thread_local! {
+    /// Principals with a payment call in flight
+    static LOCKED_PRINCIPALS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Keeps a second payment from `principal` from interleaving with one that is awaiting
+/// the ledger. The lock is released when the guard is dropped, which ic-cdk also does
+/// when a callback traps.
+pub struct PrincipalLock {
+    principal: Principal,
+}
+
+impl PrincipalLock {
+    pub fn acquire(principal: Principal) -> BackendResult<Self> {
+        LOCKED_PRINCIPALS.with(|locked| {
+            if locked.borrow_mut().insert(principal) {
+                Ok(PrincipalLock { principal })
+            } else {
+                Err(BackendError::OperationInProgress)
+            }
+        })
+    }
+}
+
+impl Drop for PrincipalLock {
+    fn drop(&mut self) {
+        LOCKED_PRINCIPALS.with(|locked| locked.borrow_mut().remove(&self.principal));
+    }
+}
@@ -0,0 +1,173 @@
+use candid::{CandidType, Nat, Principal};
+use ic_cdk::{call, id};
+use serde::Deserialize;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::config::stores::{ledger_canister_id, ledger_fee};
+
+/// ICRC-1 account, as defined by the ledger's candid interface
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct IcrcAccount {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: IcrcAccount,
+    pub to: IcrcAccount,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType)]
+pub struct TransferArgs {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: IcrcAccount,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+/// The canister's default account, where payments and swept deposits end up
+pub fn treasury_account() -> IcrcAccount {
+    IcrcAccount {
+        owner: id(),
+        subaccount: None,
+    }
+}
+
+fn nat_to_u64(value: Nat) -> BackendResult<u64> {
+    u64::try_from(&value.0)
+        .map_err(|_| BackendError::LedgerError(format!("Amount {} exceeds u64", value)))
+}
+
+/// Fee to put on transfers made on `ledger`. The configured fee applies to the default
+/// ledger; other ledgers charge their own when none is given.
+fn transfer_fee(ledger: Principal) -> Option<Nat> {
+    (ledger == ledger_canister_id()).then(|| Nat::from(ledger_fee()))
+}
+
+/// Fee `ledger` charges per transfer, in its base units
+pub async fn fee_of(ledger: Principal) -> BackendResult<Nat> {
+    if let Some(fee) = transfer_fee(ledger) {
+        return Ok(fee);
+    }
+
+    let result: Result<(Nat,), _> = call(ledger, "icrc1_fee", ()).await;
+    match result {
+        Ok((fee,)) => Ok(fee),
+        Err(err) => Err(BackendError::LedgerError(format!(
+            "Fee lookup failed: {:?}",
+            err
+        ))),
+    }
+}
+
+/// Balance of `account` on `ledger`, in base units
+pub async fn balance_of(ledger: Principal, account: IcrcAccount) -> BackendResult<Nat> {
+    let result: Result<(Nat,), _> = call(ledger, "icrc1_balance_of", (account,)).await;
+
+    match result {
+        Ok((balance,)) => Ok(balance),
+        Err(err) => Err(BackendError::LedgerError(format!(
+            "Balance check failed: {:?}",
+            err
+        ))),
+    }
+}
+
+/// Moves `amount` base units on `ledger` from one of the canister's subaccounts to
+/// `to`, paying the ledger fee on top. Returns the ledger block index of the transfer.
+pub async fn transfer(
+    ledger: Principal,
+    from_subaccount: Option<[u8; 32]>,
+    to: IcrcAccount,
+    amount: Nat,
+) -> BackendResult<u64> {
+    let args = TransferArgs {
+        from_subaccount: from_subaccount.map(|subaccount| subaccount.to_vec()),
+        to,
+        amount,
+        fee: transfer_fee(ledger),
+        memo: None,
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+
+    let result: Result<(Result<Nat, TransferError>,), _> =
+        call(ledger, "icrc1_transfer", (args,)).await;
+
+    match result {
+        Ok((Ok(block_index),)) => nat_to_u64(block_index),
+        Ok((Err(err),)) => Err(BackendError::LedgerError(format!(
+            "Transfer rejected: {:?}",
+            err
+        ))),
+        Err(err) => Err(BackendError::LedgerError(format!(
+            "Transfer failed: {:?}",
+            err
+        ))),
+    }
+}
+
+/// Pulls `amount` base units on `ledger` from `from`'s default account into the
+/// canister's treasury, using the allowance `from` granted the canister through
+/// `icrc2_approve`. Returns the ledger block index of the transfer.
+pub async fn transfer_from(ledger: Principal, from: Principal, amount: Nat) -> BackendResult<u64> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: IcrcAccount {
+            owner: from,
+            subaccount: None,
+        },
+        to: treasury_account(),
+        amount,
+        fee: transfer_fee(ledger),
+        memo: None,
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+
+    let result: Result<(Result<Nat, TransferFromError>,), _> =
+        call(ledger, "icrc2_transfer_from", (args,)).await;
+
+    match result {
+        Ok((Ok(block_index),)) => nat_to_u64(block_index),
+        Ok((Err(err),)) => Err(BackendError::LedgerError(format!(
+            "Transfer rejected: {:?}",
+            err
+        ))),
+        Err(err) => Err(BackendError::LedgerError(format!(
+            "Transfer failed: {:?}",
+            err
+        ))),
+    }
+}
@@ -1,2 +1,8 @@
 pub mod accounts;
 pub mod balance;
+pub mod deposits;
+pub mod guards;
+pub mod icrc;
+pub mod models;
+pub mod payments;
+pub mod stores;
@@ -0,0 +1,42 @@
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::common::memory::impl_candid_storable;
+use crate::config::stores::{ledger_canister_id, ledger_decimals, ledger_symbol};
+
+/// An ICRC ledger and how to display its amounts
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, Serialize)]
+pub struct TokenDescriptor {
+    pub ledger_canister_id: Principal,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl TokenDescriptor {
+    /// The configured ledger, which prices from before multi-token support are in
+    pub fn default_token() -> Self {
+        TokenDescriptor {
+            ledger_canister_id: ledger_canister_id(),
+            symbol: ledger_symbol(),
+            decimals: ledger_decimals(),
+        }
+    }
+}
+
+/// An amount in the base units of `token`
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, Serialize)]
+pub struct TokenAmount {
+    pub token: TokenDescriptor,
+    pub amount: Nat,
+}
+
+impl TokenAmount {
+    pub fn zero(token: TokenDescriptor) -> Self {
+        TokenAmount {
+            token,
+            amount: Nat::from(0u64),
+        }
+    }
+}
+
+impl_candid_storable!(TokenAmount);
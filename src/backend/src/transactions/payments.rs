@@ -0,0 +1,27 @@
+use candid::Principal;
+
+use crate::common::errors::BackendResult;
+
+use super::icrc::transfer_from;
+use super::models::TokenAmount;
+use super::stores::debit_prepaid;
+
+/// Charges `price` to `principal` on the price's ledger, from their prepaid balance
+/// there when it covers the amount and otherwise through their ICRC-2 allowance.
+/// Returns the ledger block index when the ledger was involved.
+pub async fn collect_payment(
+    principal: Principal,
+    price: &TokenAmount,
+) -> BackendResult<Option<u64>> {
+    if price.amount == 0u64 || debit_prepaid(&principal, price) {
+        return Ok(None);
+    }
+
+    transfer_from(
+        price.token.ledger_canister_id,
+        principal,
+        price.amount.clone(),
+    )
+    .await
+    .map(Some)
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::memory::{Memory, PREPAID_BALANCES_MEMORY_ID, get_memory};
+
+use super::models::TokenAmount;
+
+thread_local! {
+    /// Prepaid balances of each client, keyed by client and ledger. Credited by claimed
+    /// deposits and plan change refunds.
+    pub static PREPAID_BALANCES: RefCell<StableBTreeMap<(Principal, Principal), TokenAmount, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(PREPAID_BALANCES_MEMORY_ID)));
+}
+
+/// Every prepaid balance of `principal`, one per ledger
+pub fn prepaid_balances(principal: &Principal) -> Vec<TokenAmount> {
+    PREPAID_BALANCES.with(|balances| {
+        balances
+            .borrow()
+            .range((*principal, Principal::management_canister())..)
+            .take_while(|((owner, _), _)| owner == principal)
+            .map(|(_, balance)| balance)
+            .collect()
+    })
+}
+
+/// Adds `amount` to the prepaid balance on its ledger, returning the new balance
+pub fn credit_prepaid(principal: &Principal, amount: TokenAmount) -> TokenAmount {
+    let key = (*principal, amount.token.ledger_canister_id);
+    PREPAID_BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let balance = match balances.get(&key) {
+            Some(balance) => TokenAmount {
+                amount: balance.amount + amount.amount,
+                ..amount
+            },
+            None => amount,
+        };
+        balances.insert(key, balance.clone());
+        balance
+    })
+}
+
+/// Takes `amount` from the prepaid balance on its ledger, returning `false` and leaving
+/// it untouched when the balance is too low
+pub fn debit_prepaid(principal: &Principal, amount: &TokenAmount) -> bool {
+    let key = (*principal, amount.token.ledger_canister_id);
+    PREPAID_BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let Some(mut balance) = balances
+            .get(&key)
+            .filter(|balance| balance.amount >= amount.amount)
+        else {
+            return false;
+        };
+        balance.amount -= amount.amount.clone();
+        balances.insert(key, balance);
+        true
+    })
+}
@@ -0,0 +1,21 @@
+use crate::common::errors::BackendResult;
+use crate::users::guards::require_client;
+use crate::users::models::Client;
+
+use super::stores::{check_request_allowance, record_request};
+
+/// Runs `endpoint` for the calling client when their monthly request allowance has a
+/// request left, and counts the call against it only if it succeeds. Used by every
+/// endpoint a subscription package covers; removal and cleanup stay unmetered.
+pub fn metered<T>(endpoint: impl FnOnce(Client) -> BackendResult<T>) -> BackendResult<T> {
+    let client = require_client()?;
+    let principal = client.principal;
+    check_request_allowance(&principal)?;
+
+    let result = endpoint(client);
+    if result.is_ok() {
+        // The allowance was checked above, so counting can't fail
+        let _ = record_request(&principal);
+    }
+    result
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use ic_cdk::api::time;
+
+use super::stores::close_ended_request_periods;
+
+/// How often request periods are checked for their billing-period boundary
+const REQUEST_PERIOD_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts the job that archives request periods whose subscription term has ended.
+/// Timers don't survive upgrades, so this runs from both init and post_upgrade.
+pub fn schedule_request_period_reset() {
+    ic_cdk_timers::set_timer_interval(REQUEST_PERIOD_INTERVAL, reset_ended_request_periods);
+}
+
+fn reset_ended_request_periods() {
+    let closed = close_ended_request_periods(time());
+    if closed > 0 {
+        ic_cdk::println!("Closed {} ended request periods", closed);
+    }
+}
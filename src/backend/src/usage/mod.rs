@@ -0,0 +1,5 @@
+pub mod guards;
+pub mod jobs;
+pub mod models;
+pub mod queries;
+pub mod stores;
@@ -0,0 +1,33 @@
+use candid::CandidType;
+use serde::Deserialize;
+
+use crate::common::memory::impl_candid_storable;
+
+/// Storage of a client's live assets, in KB
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StorageUsage {
+    pub used_kb: u64,
+    /// Capacity of the active subscription package, 0 without one
+    pub capacity_kb: u64,
+    pub remaining_kb: u64,
+}
+
+/// Requests metered during one billing period
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RequestPeriod {
+    pub period_start: u64,
+    /// End of the subscription term the period was opened for
+    pub period_end: u64,
+    pub used: u64,
+    /// `monthly_requests` of the package when the period was last updated
+    pub allowance: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RequestUsageReport {
+    pub current: Option<RequestPeriod>,
+    /// Closed periods, newest first
+    pub history: Vec<RequestPeriod>,
+}
+
+impl_candid_storable!(RequestPeriod);
@@ -0,0 +1,36 @@
+use candid::Principal;
+use ic_cdk::query;
+
+use crate::common::errors::BackendResult;
+use crate::roles::{guards::require_role, models::Role};
+use crate::sessions::guards::effective_caller;
+
+use super::models::{RequestUsageReport, StorageUsage};
+use super::stores::{current_request_period, request_history, storage_usage_of};
+
+/// Storage used by the caller's live assets against their package capacity
+#[query]
+fn storage_usage() -> StorageUsage {
+    storage_usage_of(&effective_caller())
+}
+
+fn request_usage_report(principal: &Principal) -> RequestUsageReport {
+    RequestUsageReport {
+        current: current_request_period(principal),
+        history: request_history(principal),
+    }
+}
+
+/// Metered requests of the caller in the current and past billing periods
+#[query]
+fn my_request_usage() -> RequestUsageReport {
+    request_usage_report(&effective_caller())
+}
+
+/// Metered requests of any client (admins only)
+#[query]
+fn client_request_usage(principal: Principal) -> BackendResult<RequestUsageReport> {
+    require_role(Role::Admin)?;
+
+    Ok(request_usage_report(&principal))
+}
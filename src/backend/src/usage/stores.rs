@@ -0,0 +1,346 @@
+use std::cell::RefCell;
+
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::{
+    Memory, REQUEST_HISTORY_MEMORY_ID, REQUEST_USAGE_MEMORY_ID, STORAGE_USAGE_MEMORY_ID, get_memory,
+};
+use crate::users::stores::{active_package, active_subscription};
+
+use super::models::{RequestPeriod, StorageUsage};
+
+const KB_PER_MB: u64 = 1024;
+
+thread_local! {
+    /// Running total of `size_kb` over each owner's live assets. Trashed assets are
+    /// released when trashed and reserved again on restore.
+    pub static STORAGE_USAGE: RefCell<StableBTreeMap<Principal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(STORAGE_USAGE_MEMORY_ID)));
+    /// Open request period of each client
+    pub static REQUEST_USAGE: RefCell<StableBTreeMap<Principal, RequestPeriod, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(REQUEST_USAGE_MEMORY_ID)));
+    /// Closed request periods, keyed by client and period start
+    pub static REQUEST_HISTORY: RefCell<StableBTreeMap<(Principal, u64), RequestPeriod, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(REQUEST_HISTORY_MEMORY_ID)));
+}
+
+/// KB an asset of `size_mb` is accounted for, rounded up. Every change to an asset's
+/// size is accounted in these units, so the running totals never drift.
+pub fn size_kb(size_mb: f64) -> u64 {
+    (size_mb * KB_PER_MB as f64).ceil() as u64
+}
+
+pub fn storage_used_kb(owner: &Principal) -> u64 {
+    STORAGE_USAGE.with(|usage| usage.borrow().get(owner).unwrap_or_default())
+}
+
+/// Storage capacity granted by `owner`'s active subscription package
+pub fn storage_capacity_kb(owner: &Principal) -> u64 {
+    active_package(owner).map_or(0, |package| {
+        package.storage_capacity_mb.saturating_mul(KB_PER_MB)
+    })
+}
+
+pub fn storage_usage_of(owner: &Principal) -> StorageUsage {
+    let used_kb = storage_used_kb(owner);
+    let capacity_kb = storage_capacity_kb(owner);
+
+    StorageUsage {
+        used_kb,
+        capacity_kb,
+        remaining_kb: capacity_kb.saturating_sub(used_kb),
+    }
+}
+
+/// Accounts for `owner`'s storage changing from `from_kb` to `to_kb`. Growth that would
+/// exceed the package capacity fails with `QuotaExceeded` and leaves the counter
+/// unchanged; shrinking always works.
+pub fn adjust_storage(owner: &Principal, from_kb: u64, to_kb: u64) -> BackendResult<()> {
+    resize_storage(owner, from_kb, to_kb, storage_capacity_kb(owner))
+}
+
+/// `adjust_storage` against an explicit capacity
+fn resize_storage(
+    owner: &Principal,
+    from_kb: u64,
+    to_kb: u64,
+    capacity_kb: u64,
+) -> BackendResult<()> {
+    if to_kb <= from_kb {
+        release_storage(owner, from_kb - to_kb);
+        return Ok(());
+    }
+
+    let used_kb = storage_used_kb(owner) + (to_kb - from_kb);
+    if used_kb > capacity_kb {
+        return Err(BackendError::QuotaExceeded {
+            resource: "storage_kb".to_string(),
+            limit: capacity_kb,
+        });
+    }
+
+    STORAGE_USAGE.with(|usage| usage.borrow_mut().insert(*owner, used_kb));
+    Ok(())
+}
+
+/// Subtracts `size_kb` from `owner`'s usage. Releases always match an earlier
+/// reservation, so going below zero means the counter is off and is logged.
+pub fn release_storage(owner: &Principal, size_kb: u64) {
+    let used_kb = storage_used_kb(owner);
+    if size_kb > used_kb {
+        ic_cdk::println!(
+            "Releasing {} KB of {} exceeds the {} KB in use",
+            size_kb,
+            owner,
+            used_kb
+        );
+    }
+    STORAGE_USAGE.with(|usage| {
+        usage
+            .borrow_mut()
+            .insert(*owner, used_kb.saturating_sub(size_kb))
+    });
+}
+
+/// Checks that an asset size is usable for quota accounting
+pub fn validate_size_mb(size_mb: f64) -> BackendResult<()> {
+    if size_mb.is_finite() && size_mb >= 0.0 {
+        Ok(())
+    } else {
+        Err(BackendError::validation(
+            "size_mb",
+            "must be a non-negative number",
+        ))
+    }
+}
+
+fn request_allowance(owner: &Principal) -> u64 {
+    active_package(owner).map_or(0, |package| package.monthly_requests)
+}
+
+fn requests_exceeded(limit: u64) -> BackendError {
+    BackendError::QuotaExceeded {
+        resource: "monthly_requests".to_string(),
+        limit,
+    }
+}
+
+/// End of `owner`'s current subscription term, which a new request period runs to
+fn term_end(owner: &Principal) -> Option<u64> {
+    active_subscription(owner).map(|subscription| subscription.expires_at)
+}
+
+/// The period a request at `now` counts towards: the open one, or a fresh one for the
+/// rest of the term ending at `term_end` once it has ended. Fails with `QuotaExceeded`
+/// when the allowance is used up or there is no term to open a period for.
+fn request_period_at(
+    owner: &Principal,
+    now: u64,
+    allowance: u64,
+    term_end: Option<u64>,
+) -> BackendResult<RequestPeriod> {
+    let period = match REQUEST_USAGE.with(|usage| usage.borrow().get(owner)) {
+        Some(period) if period.period_end > now => period,
+        _ => RequestPeriod {
+            period_start: now,
+            period_end: term_end.ok_or_else(|| requests_exceeded(0))?,
+            used: 0,
+            allowance,
+        },
+    };
+    if period.used >= allowance {
+        return Err(requests_exceeded(allowance));
+    }
+    Ok(period)
+}
+
+/// Fails with `QuotaExceeded` when `owner` can't make another metered request, either
+/// because the allowance is used up or because they have no active subscription
+pub fn check_request_allowance(owner: &Principal) -> BackendResult<()> {
+    request_period_at(owner, time(), request_allowance(owner), term_end(owner)).map(|_| ())
+}
+
+/// Counts one request against `owner`'s package allowance, opening a new period when
+/// the previous one has ended. Fails like `check_request_allowance`.
+pub fn record_request(owner: &Principal) -> BackendResult<()> {
+    record_request_at(owner, time(), request_allowance(owner), term_end(owner))
+}
+
+fn record_request_at(
+    owner: &Principal,
+    now: u64,
+    allowance: u64,
+    term_end: Option<u64>,
+) -> BackendResult<()> {
+    let mut period = request_period_at(owner, now, allowance, term_end)?;
+    let ended = REQUEST_USAGE
+        .with(|usage| usage.borrow().get(owner))
+        .is_some_and(|open| open.period_end <= now);
+    if ended {
+        close_request_period_at(owner, now);
+    }
+    period.used += 1;
+    period.allowance = allowance;
+    REQUEST_USAGE.with(|usage| usage.borrow_mut().insert(*owner, period));
+    Ok(())
+}
+
+/// Starts a fresh period ending at `period_end`, closing the open one. Called whenever
+/// a subscription term starts.
+pub fn start_request_period(owner: &Principal, period_end: u64) {
+    close_request_period(owner);
+    let period = RequestPeriod {
+        period_start: time(),
+        period_end,
+        used: 0,
+        allowance: request_allowance(owner),
+    };
+    REQUEST_USAGE.with(|usage| usage.borrow_mut().insert(*owner, period));
+}
+
+/// Moves `owner`'s open period into the history. A period cut short by a new
+/// subscription term ends now.
+pub fn close_request_period(owner: &Principal) {
+    close_request_period_at(owner, time());
+}
+
+fn close_request_period_at(owner: &Principal, now: u64) {
+    let Some(mut period) = REQUEST_USAGE.with(|usage| usage.borrow_mut().remove(owner)) else {
+        return;
+    };
+    period.period_end = period.period_end.min(now);
+    REQUEST_HISTORY.with(|history| {
+        history
+            .borrow_mut()
+            .insert((*owner, period.period_start), period)
+    });
+}
+
+/// Closes every period that ended before `now`, returning how many were closed
+pub fn close_ended_request_periods(now: u64) -> u64 {
+    let ended: Vec<Principal> = REQUEST_USAGE.with(|usage| {
+        usage
+            .borrow()
+            .iter()
+            .filter(|(_, period)| period.period_end <= now)
+            .map(|(owner, _)| owner)
+            .collect()
+    });
+    for owner in &ended {
+        close_request_period_at(owner, now);
+    }
+    ended.len() as u64
+}
+
+/// `owner`'s open period, with the allowance of their current package
+pub fn current_request_period(owner: &Principal) -> Option<RequestPeriod> {
+    REQUEST_USAGE
+        .with(|usage| usage.borrow().get(owner))
+        .filter(|period| period.period_end > time())
+        .map(|period| RequestPeriod {
+            allowance: request_allowance(owner),
+            ..period
+        })
+}
+
+/// `owner`'s closed periods, newest first
+pub fn request_history(owner: &Principal) -> Vec<RequestPeriod> {
+    let mut periods: Vec<RequestPeriod> = REQUEST_HISTORY.with(|history| {
+        history
+            .borrow()
+            .range((*owner, 0)..=(*owner, u64::MAX))
+            .map(|(_, period)| period)
+            .collect()
+    });
+    periods.reverse();
+    periods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_stop_at_the_allowance() {
+        let owner = Principal::from_slice(&[3]);
+        assert!(matches!(
+            record_request_at(&owner, 10, 2, None),
+            Err(BackendError::QuotaExceeded { limit: 0, .. })
+        ));
+
+        record_request_at(&owner, 10, 2, Some(100)).unwrap();
+        record_request_at(&owner, 20, 2, Some(100)).unwrap();
+        assert!(matches!(
+            record_request_at(&owner, 30, 2, Some(100)),
+            Err(BackendError::QuotaExceeded { limit: 2, .. })
+        ));
+        let period = REQUEST_USAGE
+            .with(|usage| usage.borrow().get(&owner))
+            .unwrap();
+        assert_eq!(period.used, 2);
+    }
+
+    #[test]
+    fn requests_roll_over_into_a_new_period() {
+        let owner = Principal::from_slice(&[4]);
+        record_request_at(&owner, 10, 5, Some(100)).unwrap();
+        record_request_at(&owner, 50, 5, Some(100)).unwrap();
+
+        record_request_at(&owner, 150, 5, Some(300)).unwrap();
+        let current = REQUEST_USAGE
+            .with(|usage| usage.borrow().get(&owner))
+            .unwrap();
+        assert_eq!(
+            (current.period_start, current.period_end, current.used),
+            (150, 300, 1)
+        );
+        let history = request_history(&owner);
+        assert_eq!(history.len(), 1);
+        assert_eq!(
+            (
+                history[0].period_start,
+                history[0].period_end,
+                history[0].used
+            ),
+            (10, 100, 2)
+        );
+
+        assert_eq!(close_ended_request_periods(300), 1);
+        assert_eq!(request_history(&owner).len(), 2);
+    }
+
+    #[test]
+    fn storage_quota_is_enforced_in_whole_kb() {
+        let owner = Principal::from_slice(&[1]);
+        assert_eq!(size_kb(0.1), 103);
+        assert_eq!(size_kb(1.0), 1_024);
+
+        resize_storage(&owner, 0, 1_024, 2_048).unwrap();
+        let err = resize_storage(&owner, 0, 1_025, 2_048).unwrap_err();
+        assert!(matches!(
+            err,
+            BackendError::QuotaExceeded { limit: 2_048, .. }
+        ));
+        assert_eq!(storage_used_kb(&owner), 1_024);
+
+        resize_storage(&owner, 0, 1_024, 2_048).unwrap();
+        // Shrinking works even over capacity
+        resize_storage(&owner, 1_024, 0, 0).unwrap();
+        assert_eq!(storage_used_kb(&owner), 1_024);
+    }
+
+    #[test]
+    fn storage_totals_do_not_drift() {
+        let owner = Principal::from_slice(&[2]);
+        for _ in 0..10 {
+            resize_storage(&owner, 0, size_kb(0.1), u64::MAX).unwrap();
+        }
+        for _ in 0..10 {
+            release_storage(&owner, size_kb(0.1));
+        }
+        assert_eq!(storage_used_kb(&owner), 0);
+    }
+}
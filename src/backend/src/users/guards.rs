@@ -0,0 +1,13 @@
+use crate::common::errors::{BackendError, BackendResult};
+use crate::sessions::guards::effective_caller;
+
+use super::models::Client;
+use super::stores::CLIENTS;
+
+/// Returns the `Client` record of the caller, or of the client a session caller acts
+/// for, failing with `Unauthorized` for non-clients
+pub fn require_client() -> BackendResult<Client> {
+    CLIENTS
+        .with(|clients| clients.borrow().get(&effective_caller()))
+        .ok_or(BackendError::Unauthorized)
+}
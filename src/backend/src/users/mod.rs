@@ -1,3 +1,4 @@
+pub mod guards;
 pub mod models;
 pub mod mutations;
 pub mod queries;
@@ -1,3 +1,11 @@
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::memory::impl_candid_storable;
+use crate::migrations::v2_numeric_timestamps::LegacyProfile;
+use crate::migrations::v4_token_amounts::{
+    LegacyClientPackageSubscription, LegacySubscriptionPackage,
+};
+use crate::transactions::models::{TokenAmount, TokenDescriptor};
+use crate::usage::models::{RequestPeriod, StorageUsage};
 use candid::*;
 use serde::{Deserialize, Serialize};
 
@@ -8,15 +16,16 @@ pub struct Profile {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub image_hash: Option<String>,
-    pub date_added: String,
-    pub last_updated: String,
+    pub date_added: u64,
+    pub last_updated: u64,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct SubscriptionPackage {
     pub uuid: String,
     pub name: String,
-    pub price: f64,
+    /// Price per term in each accepted token, at most one per ledger. Free when empty.
+    pub prices: Vec<TokenAmount>,
     pub storage_capacity_mb: u64,
     pub monthly_requests: u64,
     pub max_allowed_sessions: u64,
@@ -34,6 +43,113 @@ pub struct Client {
 pub struct ClientPackageSubscription {
     pub client_uuid: String,
     pub subscription_package_uuid: String,
-    pub amount: f64,
+    /// Paid for the current term, in base units of `token`
+    pub amount: Nat,
+    /// Token the subscription is paid and renewed in
+    pub token: TokenDescriptor,
     pub expires_at: u64,
+    /// Ledger block of the payment, `None` for free packages and prepaid payments
+    pub block_index: Option<u64>,
+    /// Start of the current term
+    pub period_start: Option<u64>,
+    /// Whether the renewal job charges the client for another term, off by default
+    pub auto_renew: Option<bool>,
+    /// Lifecycle state maintained by the renewal job, `None` means `Active`
+    pub state: Option<SubscriptionState>,
+    /// Package the next renewal switches to, set by end-of-period plan changes
+    pub pending_package_uuid: Option<String>,
+    /// Set for unpaid trial terms, which convert to paid ones on renewal
+    pub trial: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscriptionState {
+    Active,
+    /// The term ended and renewal is still possible
+    Grace,
+    /// The grace period passed without auto-renew
+    Expired,
+    /// The grace period passed with auto-renew on but no successful payment
+    Suspended,
+}
+
+/// Subscription of a client as seen by dashboards
+#[derive(CandidType, Deserialize, Clone)]
+pub enum SubscriptionStatus {
+    /// The caller never subscribed
+    None,
+    Active(ActiveSubscription),
+    /// The term ended and can still be renewed until `grace_ends_at`
+    Grace {
+        subscription_package_uuid: String,
+        expired_at: u64,
+        grace_ends_at: u64,
+    },
+    Expired {
+        subscription_package_uuid: String,
+        expired_at: u64,
+    },
+    /// Renewal payments failed until the grace period passed
+    Suspended {
+        subscription_package_uuid: String,
+        expired_at: u64,
+    },
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ActiveSubscription {
+    pub package: SubscriptionPackage,
+    pub period_start: u64,
+    pub period_end: u64,
+    /// Whole days left in the term
+    pub remaining_days: u64,
+    pub auto_renew: bool,
+    pub pending_package_uuid: Option<String>,
+    /// Whether this is an unpaid trial term
+    pub trial: bool,
+    pub storage: StorageUsage,
+    /// `None` until the first metered request of the term
+    pub requests: Option<RequestPeriod>,
 }
+
+impl SubscriptionPackage {
+    /// Whether the package costs nothing in every token
+    pub fn is_free(&self) -> bool {
+        self.prices.iter().all(|price| price.amount == 0u64)
+    }
+
+    /// Price of a term paid on `ledger`, or on the default ledger when `None`. Packages
+    /// not priced in the default token fall back to their first price.
+    pub fn price_in(&self, ledger: Option<Principal>) -> BackendResult<TokenAmount> {
+        if self.prices.is_empty() {
+            return Ok(TokenAmount::zero(TokenDescriptor::default_token()));
+        }
+        let priced_on = |ledger: Principal| {
+            self.prices
+                .iter()
+                .find(move |price| price.token.ledger_canister_id == ledger)
+        };
+        let price = match ledger {
+            Some(ledger) => priced_on(ledger),
+            None => priced_on(TokenDescriptor::default_token().ledger_canister_id)
+                .or(self.prices.first()),
+        };
+
+        price.cloned().ok_or_else(|| {
+            BackendError::validation("token", "the package isn't priced in this token")
+        })
+    }
+}
+
+impl ClientPackageSubscription {
+    pub fn state(&self) -> SubscriptionState {
+        self.state.unwrap_or(SubscriptionState::Active)
+    }
+}
+
+impl_candid_storable!(
+    Profile | LegacyProfile,
+    SubscriptionPackage | LegacySubscriptionPackage,
+    Client,
+    ClientPackageSubscription | LegacyClientPackageSubscription
+);
@@ -1,21 +1,41 @@
+use candid::Principal;
 use ic_cdk::{caller, update};
 
-use crate::common::utils::uuid::generate_unique_id;
+use crate::common::errors::{BackendError, BackendResult};
+use crate::common::utils::uuid::{IdKind, generate_unique_id};
+use crate::config::stores::{free_package_uuid, subscription_duration_ns};
+use crate::promotions::models::PromoRedemption;
+use crate::promotions::stores::{
+    apply_discount, record_redemption, release_promo_code, reserve_promo_code,
+};
+use crate::roles::{guards::require_role, models::Role};
+use crate::sessions::stores::is_session;
+use crate::subscriptions::models::{InvoiceStatus, SubscriptionEventKind};
+use crate::subscriptions::stores::{Charge, is_paid_term, issue_invoice, record_event};
+use crate::subscriptions::terms::{NewTerm, assign_free_package, start_term};
+use crate::transactions::guards::PrincipalLock;
+use crate::transactions::models::TokenAmount;
+use crate::transactions::payments::collect_payment;
 
-use super::models::{Client, ClientPackageSubscription, Profile, SubscriptionPackage};
-use super::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES, USERS};
+use super::models::{ClientPackageSubscription, Profile, SubscriptionPackage};
+use super::stores::{SUBSCRIPTION_PACKAGES, USERS, active_subscription};
 
-/// Authenticate the caller and create an empty profile if they don’t have one
+/// Authenticate the caller and create an empty profile if they don’t have one. New
+/// users are put on the free package when one is configured.
 #[update]
-fn register() -> String {
+fn register() -> BackendResult<Profile> {
     let user_principal = caller();
-    let current_time = ic_cdk::api::time().to_string();
+    // Sessions act for their client and can't become users themselves
+    if is_session(&user_principal) {
+        return Err(BackendError::Unauthorized);
+    }
+    let current_time = ic_cdk::api::time();
 
-    USERS.with(|users| {
+    let profile = USERS.with(|users| {
         let mut users = users.borrow_mut();
 
         if users.contains_key(&user_principal) {
-            return format!("User already registered with principal: {}", user_principal);
+            return Err(BackendError::already_exists("user", user_principal));
         }
 
         let new_profile = Profile {
@@ -24,16 +44,16 @@ fn register() -> String {
             first_name: None,
             last_name: None,
             image_hash: None,
-            date_added: current_time.clone(),
+            date_added: current_time,
             last_updated: current_time,
         };
 
-        users.insert(user_principal, new_profile);
-        format!(
-            "User registered successfully with principal: {}",
-            user_principal
-        )
-    })
+        users.insert(user_principal, new_profile.clone());
+        Ok(new_profile)
+    })?;
+
+    assign_free_package(user_principal);
+    Ok(profile)
 }
 
 /// Update profile details (only the caller can update their own profile)
@@ -43,44 +63,73 @@ fn update_profile(
     first_name: Option<String>,
     last_name: Option<String>,
     image_hash: Option<String>,
-) -> String {
+) -> BackendResult<Profile> {
     let user_principal = caller();
-    let current_time = ic_cdk::api::time().to_string();
+    let current_time = ic_cdk::api::time();
 
     USERS.with(|users| {
         let mut users = users.borrow_mut();
 
-        if let Some(profile) = users.get_mut(&user_principal) {
-            profile.email = email.or(profile.email.clone());
-            profile.first_name = first_name.or(profile.first_name.clone());
-            profile.last_name = last_name.or(profile.last_name.clone());
-            profile.image_hash = image_hash.or(profile.image_hash.clone());
-            profile.last_updated = current_time;
-
-            return "Profile updated successfully".to_string();
-        }
+        let mut profile = users
+            .get(&user_principal)
+            .ok_or(BackendError::NotRegistered)?;
+        profile.email = email.or(profile.email);
+        profile.first_name = first_name.or(profile.first_name);
+        profile.last_name = last_name.or(profile.last_name);
+        profile.image_hash = image_hash.or(profile.image_hash);
+        profile.last_updated = current_time;
+        users.insert(user_principal, profile.clone());
 
-        "User not found".to_string()
+        Ok(profile)
     })
 }
 
-/// Creates or updates a subscription package
+/// Creates or updates a subscription package (admins only)
 #[update]
 fn create_update_subscription_package(
     uuid: Option<String>,
     name: String,
-    price: f64,
+    prices: Vec<TokenAmount>,
     storage_capacity_mb: u64,
     monthly_requests: u64,
     max_allowed_sessions: u64,
-) -> String {
-    let uuid = uuid.unwrap_or_else(generate_unique_id);
+) -> BackendResult<SubscriptionPackage> {
+    require_role(Role::Admin)?;
+
+    for (index, price) in prices.iter().enumerate() {
+        if price.token.symbol.trim().is_empty() {
+            return Err(BackendError::validation(
+                "prices",
+                "every token needs a symbol",
+            ));
+        }
+        let ledger = price.token.ledger_canister_id;
+        if prices[..index]
+            .iter()
+            .any(|other| other.token.ledger_canister_id == ledger)
+        {
+            return Err(BackendError::validation(
+                "prices",
+                format!("more than one price on ledger {}", ledger),
+            ));
+        }
+    }
+
+    let is_free = prices.iter().all(|price| price.amount == 0u64);
+    if !is_free && uuid.is_some() && uuid == free_package_uuid() {
+        return Err(BackendError::validation(
+            "prices",
+            "the configured free package must stay priced at zero",
+        ));
+    }
+
+    let uuid = uuid.unwrap_or_else(|| generate_unique_id(IdKind::SubscriptionPackage));
     let current_time = ic_cdk::api::time();
 
     let package = SubscriptionPackage {
         uuid: uuid.clone(),
         name,
-        price,
+        prices,
         storage_capacity_mb,
         monthly_requests,
         max_allowed_sessions,
@@ -88,57 +137,124 @@ fn create_update_subscription_package(
     };
 
     SUBSCRIPTION_PACKAGES.with(|packages| {
-        packages.borrow_mut().insert(uuid.clone(), package);
+        packages.borrow_mut().insert(uuid, package.clone());
     });
 
-    format!("Subscription package {} created/updated.", uuid)
+    Ok(package)
 }
 
-/// Allows a user to subscribe to a package
+/// Subscribes the caller to a package, paid in the token of the `token` ledger or in
+/// the package's default token when `None`. The price comes out of the caller's
+/// prepaid balance in that token when it suffices, otherwise it is pulled from their
+/// account on that ledger with `icrc2_transfer_from`, so the caller must first approve
+/// the canister for at least the price plus the ledger fee. The subscription only
+/// starts once paid, and renews in the same token. Clients in the middle of a paid term
+/// switch packages with `change_plan` instead.
+///
+/// `promo_code` discounts this first term; renewals are charged the full price.
 #[update]
-fn create_update_client_package_subscription(subscription_package_uuid: String) -> String {
+async fn create_update_client_package_subscription(
+    subscription_package_uuid: String,
+    token: Option<Principal>,
+    promo_code: Option<String>,
+) -> BackendResult<ClientPackageSubscription> {
     let user_principal = caller();
-    let current_time = ic_cdk::api::time();
-    let expires_at = current_time + (30 * 24 * 60 * 60 * 1_000_000_000); // 30 days in nanoseconds
+    // Sessions act for their client and can't become clients themselves
+    if is_session(&user_principal) {
+        return Err(BackendError::Unauthorized);
+    }
 
     // Check if package exists
-    let package_price = SUBSCRIPTION_PACKAGES.with(|packages| {
-        packages
-            .borrow()
-            .get(&subscription_package_uuid)
-            .map(|p| p.price)
-    });
+    let full_price = SUBSCRIPTION_PACKAGES
+        .with(|packages| packages.borrow().get(&subscription_package_uuid))
+        .ok_or_else(|| BackendError::not_found("subscription_package", &subscription_package_uuid))?
+        .price_in(token)?;
 
-    if package_price.is_none() {
-        return "Subscription package not found.".to_string();
+    // Held until the subscription is stored, so a concurrent call can't pay twice
+    let _lock = PrincipalLock::acquire(user_principal)?;
+    // A new term would forfeit the rest of a paid one, `change_plan` settles it instead
+    if active_subscription(&user_principal)
+        .is_some_and(|subscription| is_paid_term(&user_principal, &subscription))
+    {
+        return Err(BackendError::validation(
+            "subscription",
+            "already on a paid term, use change_plan to switch packages",
+        ));
     }
+    // Nothing to discount, and a redemption would be wasted on it
+    if promo_code.is_some() && full_price.amount == 0u64 {
+        return Err(BackendError::validation(
+            "promo_code",
+            "free packages can't be discounted",
+        ));
+    }
+    let promo = promo_code
+        .map(|code| {
+            reserve_promo_code(
+                &code,
+                &user_principal,
+                &subscription_package_uuid,
+                &full_price,
+            )
+        })
+        .transpose()?;
+    let price = TokenAmount {
+        amount: promo.as_ref().map_or(full_price.amount.clone(), |promo| {
+            apply_discount(&full_price, &promo.discount)
+        }),
+        token: full_price.token.clone(),
+    };
 
-    let price = package_price.unwrap();
-
-    // Insert or update the client
-    CLIENTS.with(|clients| {
-        let mut clients = clients.borrow_mut();
-        let client = clients.entry(user_principal).or_insert(Client {
-            principal: user_principal,
-            uuid: generate_unique_id(),
-            active_subscription_uuid: None,
-        });
-
-        // Store subscription
-        let client_subscription = ClientPackageSubscription {
-            client_uuid: client.uuid.clone(),
+    let block_index = match collect_payment(user_principal, &price).await {
+        Ok(block_index) => block_index,
+        Err(err) => {
+            if let Some(promo) = &promo {
+                release_promo_code(&promo.code);
+            }
+            return Err(err);
+        }
+    };
+    if let Some(promo) = &promo {
+        record_redemption(PromoRedemption {
+            code: promo.code.clone(),
+            client_principal: user_principal,
             subscription_package_uuid: subscription_package_uuid.clone(),
-            amount: price,
-            expires_at,
-        };
-
-        CLIENT_SUBSCRIPTIONS.with(|subs| {
-            subs.borrow_mut()
-                .insert(client.uuid.clone(), client_subscription);
+            discount_amount: TokenAmount {
+                amount: full_price.amount.clone() - price.amount.clone(),
+                token: price.token.clone(),
+            },
+            redeemed_at: ic_cdk::api::time(),
         });
+    }
 
-        client.active_subscription_uuid = Some(subscription_package_uuid);
+    let client_subscription = start_term(NewTerm {
+        client_principal: user_principal,
+        subscription_package_uuid: &subscription_package_uuid,
+        price: price.clone(),
+        duration_ns: subscription_duration_ns(),
+        block_index,
+        trial: false,
+        auto_renew: None,
     });
 
-    format!("Subscription successful for principal: {}", user_principal)
+    record_event(
+        user_principal,
+        &subscription_package_uuid,
+        SubscriptionEventKind::Subscribed { block_index },
+    );
+    issue_invoice(
+        Charge {
+            client_principal: user_principal,
+            client_uuid: &client_subscription.client_uuid,
+            subscription_package_uuid: &subscription_package_uuid,
+            period_start: client_subscription.period_start.unwrap_or_default(),
+            period_end: client_subscription.expires_at,
+            price,
+            block_index,
+            promo_code: promo.map(|promo| promo.code),
+        },
+        InvoiceStatus::Paid,
+    );
+
+    Ok(client_subscription)
 }
@@ -1,65 +1,108 @@
 use candid::Principal;
 use ic_cdk::{caller, query};
 
+use crate::common::errors::{BackendError, BackendResult};
+use crate::config::stores::{grace_period_ns, subscription_duration_ns};
+use crate::sessions::guards::effective_caller;
+use crate::usage::stores::{current_request_period, storage_usage_of};
 use crate::{Client, SubscriptionPackage};
 
-use super::models::Profile;
+use super::models::{ActiveSubscription, Profile, SubscriptionState, SubscriptionStatus};
 use super::stores::{CLIENT_SUBSCRIPTIONS, CLIENTS, SUBSCRIPTION_PACKAGES, USERS};
 
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
 #[query]
 fn get_profile() -> Option<Profile> {
     let user_principal = caller();
-    USERS.with(|users| users.borrow().get(&user_principal).cloned())
+    USERS.with(|users| users.borrow().get(&user_principal))
 }
 
 #[query]
 fn get_profile_by_principal(principal: Principal) -> Option<Profile> {
-    USERS.with(|users| users.borrow().get(&principal).cloned())
+    USERS.with(|users| users.borrow().get(&principal))
 }
 
-/// Check if a user has an active subscription
+/// Subscription status of the caller, or of the client a session acts for. The
+/// renewal job updates the stored state hourly, so the status is derived from the
+/// term's dates as well. Fails when the package of an active term is missing.
 #[query]
-fn check_subscription_status() -> String {
-    let user_principal = caller();
+fn check_subscription_status() -> BackendResult<SubscriptionStatus> {
+    let user_principal = effective_caller();
+    let Some(client) = CLIENTS.with(|clients| clients.borrow().get(&user_principal)) else {
+        return Ok(SubscriptionStatus::None);
+    };
+    let Some(subscription) = CLIENT_SUBSCRIPTIONS.with(|subs| subs.borrow().get(&client.uuid))
+    else {
+        return Ok(SubscriptionStatus::None);
+    };
 
-    CLIENTS.with(|clients| {
-        let clients = clients.borrow();
-        if let Some(client) = clients.get(&user_principal) {
-            if let Some(sub_uuid) = &client.active_subscription_uuid {
-                CLIENT_SUBSCRIPTIONS.with(|subs| {
-                    let subs = subs.borrow();
-                    if let Some(subscription) = subs.get(sub_uuid) {
-                        let current_time = ic_cdk::api::time();
-                        if subscription.expires_at > current_time {
-                            format!(
-                                "Subscription is active. Expires at: {}",
-                                subscription.expires_at
-                            )
-                        } else {
-                            "Subscription has expired.".to_string()
-                        }
-                    } else {
-                        "Subscription not found".to_string()
-                    }
-                });
+    let now = ic_cdk::api::time();
+    let subscription_package_uuid = subscription.subscription_package_uuid.clone();
+    let expired_at = subscription.expires_at;
+    let grace_ends_at = expired_at + grace_period_ns();
+    let auto_renew = subscription.auto_renew.unwrap_or(false);
+
+    let status = match subscription.state() {
+        SubscriptionState::Active | SubscriptionState::Grace if expired_at > now => {
+            // Packages are never removed, a missing one means a corrupt record
+            let package = SUBSCRIPTION_PACKAGES
+                .with(|packages| packages.borrow().get(&subscription_package_uuid))
+                .ok_or_else(|| {
+                    BackendError::not_found("subscription_package", &subscription_package_uuid)
+                })?;
+            SubscriptionStatus::Active(ActiveSubscription {
+                package,
+                period_start: subscription
+                    .period_start
+                    .unwrap_or_else(|| expired_at.saturating_sub(subscription_duration_ns())),
+                period_end: expired_at,
+                remaining_days: (expired_at - now) / NANOS_PER_DAY,
+                auto_renew,
+                pending_package_uuid: subscription.pending_package_uuid,
+                trial: subscription.trial.unwrap_or(false),
+                storage: storage_usage_of(&user_principal),
+                requests: current_request_period(&user_principal),
+            })
+        }
+        SubscriptionState::Active | SubscriptionState::Grace if grace_ends_at > now => {
+            SubscriptionStatus::Grace {
+                subscription_package_uuid,
+                expired_at,
+                grace_ends_at,
             }
         }
-        "No active subscription found.".to_string()
-    })
+        SubscriptionState::Suspended => SubscriptionStatus::Suspended {
+            subscription_package_uuid,
+            expired_at,
+        },
+        // The grace period passed, possibly before the renewal job got to it
+        _ if auto_renew && subscription.state() != SubscriptionState::Expired => {
+            SubscriptionStatus::Suspended {
+                subscription_package_uuid,
+                expired_at,
+            }
+        }
+        _ => SubscriptionStatus::Expired {
+            subscription_package_uuid,
+            expired_at,
+        },
+    };
+    Ok(status)
 }
 
 #[query]
 fn subscription_packages() -> Vec<SubscriptionPackage> {
-    SUBSCRIPTION_PACKAGES.with(|packages| packages.borrow().values().cloned().collect())
+    SUBSCRIPTION_PACKAGES.with(|packages| packages.borrow().values().collect())
 }
 
-/// Query to get the client associated with the caller
+/// Query to get the client associated with the caller, or the one a session acts for
 #[query]
 fn get_client() -> Option<Client> {
-    let user_principal = caller();
+    let user_principal = effective_caller();
 
     CLIENTS.with(|clients| {
         let clients = clients.borrow();
-        clients.get(&user_principal).cloned()
+        clients.get(&user_principal)
     })
 }
@@ -1,52 +1,55 @@
 use super::models::{Client, ClientPackageSubscription, Profile, SubscriptionPackage};
+use crate::common::memory::{
+    CLIENT_SUBSCRIPTIONS_MEMORY_ID, CLIENTS_MEMORY_ID, Memory, SUBSCRIPTION_PACKAGES_MEMORY_ID,
+    USERS_MEMORY_ID, get_memory,
+};
+use crate::transactions::models::TokenDescriptor;
 use candid::Principal;
-use ic_cdk::storage;
-use std::{cell::RefCell, collections::HashMap};
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
 
 thread_local! {
-    pub static USERS: RefCell<HashMap<Principal, Profile>> = RefCell::new(HashMap::new());
-    pub static CLIENTS: RefCell<HashMap<Principal, Client>> = RefCell::new(HashMap::new());
-    pub static SUBSCRIPTION_PACKAGES: RefCell<HashMap<String, SubscriptionPackage>> = RefCell::new(HashMap::new());
-    pub static CLIENT_SUBSCRIPTIONS: RefCell<HashMap<String, ClientPackageSubscription>> = RefCell::new(HashMap::new());
+    pub static USERS: RefCell<StableBTreeMap<Principal, Profile, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(USERS_MEMORY_ID)));
+    pub static CLIENTS: RefCell<StableBTreeMap<Principal, Client, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(CLIENTS_MEMORY_ID)));
+    pub static SUBSCRIPTION_PACKAGES: RefCell<StableBTreeMap<String, SubscriptionPackage, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SUBSCRIPTION_PACKAGES_MEMORY_ID)));
+    pub static CLIENT_SUBSCRIPTIONS: RefCell<StableBTreeMap<String, ClientPackageSubscription, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(CLIENT_SUBSCRIPTIONS_MEMORY_ID)));
 }
 
-/// Initialize empty state
-#[ic_cdk::init]
-fn init() {
-    USERS.with(|users| *users.borrow_mut() = HashMap::new());
-    CLIENTS.with(|clients| *clients.borrow_mut() = HashMap::new());
-    SUBSCRIPTION_PACKAGES.with(|packages| *packages.borrow_mut() = HashMap::new());
-    CLIENT_SUBSCRIPTIONS.with(|subscriptions| *subscriptions.borrow_mut() = HashMap::new());
+/// `principal`'s current subscription, if it hasn't expired yet
+pub fn active_subscription(principal: &Principal) -> Option<ClientPackageSubscription> {
+    let client = CLIENTS.with(|clients| clients.borrow().get(principal))?;
+    CLIENT_SUBSCRIPTIONS
+        .with(|subscriptions| subscriptions.borrow().get(&client.uuid))
+        .filter(|subscription| subscription.expires_at > ic_cdk::api::time())
 }
 
-/// Save state before upgrade
-#[ic_cdk::pre_upgrade]
-fn save_state() {
-    let users = USERS.with(|users| users.borrow().clone());
-    let clients = CLIENTS.with(|clients| clients.borrow().clone());
-    let subscription_packages = SUBSCRIPTION_PACKAGES.with(|packages| packages.borrow().clone());
-    let client_subscriptions =
-        CLIENT_SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().clone());
-
-    storage::stable_save((users, clients, subscription_packages, client_subscriptions))
-        .expect("Failed to save state");
+/// The package backing `principal`'s current, unexpired subscription
+pub fn active_package(principal: &Principal) -> Option<SubscriptionPackage> {
+    let subscription = active_subscription(principal)?;
+    SUBSCRIPTION_PACKAGES.with(|packages| {
+        packages
+            .borrow()
+            .get(&subscription.subscription_package_uuid)
+    })
 }
 
-type Type = (
-    HashMap<Principal, Profile>,
-    HashMap<Principal, Client>,
-    HashMap<String, SubscriptionPackage>,
-    HashMap<String, ClientPackageSubscription>,
-);
-
-/// Restore state after upgrade
-#[ic_cdk::post_upgrade]
-fn restore_state() {
-    let (users, clients, subscription_packages, client_subscriptions): Type =
-        storage::stable_restore().expect("Failed to restore state");
-
-    USERS.with(|state| *state.borrow_mut() = users);
-    CLIENTS.with(|state| *state.borrow_mut() = clients);
-    SUBSCRIPTION_PACKAGES.with(|state| *state.borrow_mut() = subscription_packages);
-    CLIENT_SUBSCRIPTIONS.with(|state| *state.borrow_mut() = client_subscriptions);
+/// Token of `ledger` if any package is priced in it, or the default token when `None`
+pub fn accepted_token(ledger: Option<Principal>) -> Option<TokenDescriptor> {
+    let default_token = TokenDescriptor::default_token();
+    let ledger = match ledger {
+        Some(ledger) if ledger != default_token.ledger_canister_id => ledger,
+        _ => return Some(default_token),
+    };
+    SUBSCRIPTION_PACKAGES.with(|packages| {
+        packages
+            .borrow()
+            .values()
+            .flat_map(|package| package.prices)
+            .map(|price| price.token)
+            .find(|token| token.ledger_canister_id == ledger)
+    })
 }